@@ -1,5 +1,6 @@
 pub mod pinger;
+pub mod resolver;
 
 pub mod prelude {
-    pub use super::pinger::*;
+    pub use super::{pinger::*, resolver::*};
 }
@@ -0,0 +1,268 @@
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{anyhow, bail};
+use trust_dns_resolver::{
+    config::{NameServerConfig, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    proto::{
+        op::Query,
+        rr::{RData, Record, RecordType},
+    },
+    Hosts, Name, TokioAsyncResolver,
+};
+
+/// Where the answer to a lookup came from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResolutionSource {
+    /// The input was already an IP address
+    Literal,
+    /// A static entry in the system hosts file
+    HostsFile { name: String },
+    /// A configured name server answered for the given (possibly search-expanded) name
+    NameServer { name: String, addr: SocketAddr },
+}
+
+impl std::fmt::Display for ResolutionSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolutionSource::Literal => write!(f, "literal IP address"),
+            ResolutionSource::HostsFile { name } => write!(f, "hosts file entry for '{name}'"),
+            ResolutionSource::NameServer { name, addr } => {
+                write!(f, "name server {addr} for '{name}'")
+            }
+        }
+    }
+}
+
+/// The records of a lookup along with how they were found
+pub struct TracedLookup {
+    pub records: Vec<Record>,
+    pub source: ResolutionSource,
+    /// Every name queried, in order, after search domain expansion
+    pub tried: Vec<Name>,
+}
+
+impl TracedLookup {
+    /// IP addresses in the answer, skipping any CNAMEs along the way
+    pub fn ips(&self) -> Vec<IpAddr> {
+        self.records
+            .iter()
+            .filter_map(|r| match r.data() {
+                Some(RData::A(a)) => Some(IpAddr::V4(a.0)),
+                Some(RData::AAAA(aaaa)) => Some(IpAddr::V6(aaaa.0)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Resolves names one step at a time, the way the system stub resolver would:
+/// hosts file first, then each search domain expansion against each configured name server in order.
+pub struct TracingResolver {
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    hosts: Option<Hosts>,
+    name_servers: Vec<(SocketAddr, TokioAsyncResolver)>,
+}
+
+impl TracingResolver {
+    pub fn new(config: ResolverConfig, opts: ResolverOpts) -> Self {
+        let mut ns_opts = opts;
+        // search expansion and the hosts file are handled here so each step can be reported
+        ns_opts.ndots = 0;
+        ns_opts.use_hosts_file = false;
+
+        let mut name_servers: Vec<(SocketAddr, TokioAsyncResolver)> = Vec::new();
+        for ns in config.name_servers() {
+            if name_servers.iter().any(|(addr, _)| *addr == ns.socket_addr) {
+                // system config lists each server once per protocol
+                continue;
+            }
+            let mut ns_config = ResolverConfig::new();
+            ns_config.add_name_server(NameServerConfig::new(ns.socket_addr, ns.protocol));
+            name_servers.push((
+                ns.socket_addr,
+                TokioAsyncResolver::tokio(ns_config, ns_opts),
+            ));
+        }
+
+        Self {
+            hosts: opts.use_hosts_file.then(Hosts::new),
+            config,
+            opts,
+            name_servers,
+        }
+    }
+
+    pub fn from_system_conf() -> anyhow::Result<Self> {
+        let (config, opts) = trust_dns_resolver::system_conf::read_system_conf()
+            .map_err(|e| anyhow!("failed to read system DNS config: {e}"))?;
+        Ok(Self::new(config, opts))
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    pub fn opts(&self) -> &ResolverOpts {
+        &self.opts
+    }
+
+    /// Names to query for the input, in order, following the ndots and search list rules
+    pub fn candidate_names(&self, name: &str) -> anyhow::Result<Vec<Name>> {
+        let name =
+            Name::from_str_relaxed(name).map_err(|e| anyhow!("invalid name '{name}': {e}"))?;
+        if name.is_fqdn() {
+            return Ok(vec![name]);
+        }
+
+        let mut search_names = Vec::with_capacity(self.config.search().len() + 1);
+        for search in self.config.search().iter().chain(self.config.domain()) {
+            if let Ok(search_name) = name.clone().append_domain(search) {
+                if !search_names.contains(&search_name) {
+                    search_names.push(search_name);
+                }
+            }
+        }
+
+        let mut raw_name = name.clone();
+        raw_name.set_fqdn(true);
+        let mut names = Vec::with_capacity(search_names.len() + 1);
+        if name.num_labels() as usize > self.opts.ndots || name.is_localhost() {
+            names.push(raw_name.clone());
+            names.extend(search_names.into_iter().filter(|n| *n != raw_name));
+        } else {
+            names.extend(search_names);
+            if !names.contains(&raw_name) {
+                names.push(raw_name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Look up records for the name, a negative answer is returned with no records and the
+    /// name server that gave it as the source
+    pub async fn lookup(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> anyhow::Result<TracedLookup> {
+        let mut tried = Vec::new();
+        let mut negative_answer = None;
+        let mut last_error: Option<ResolveError> = None;
+
+        for candidate in self.candidate_names(name)? {
+            tried.push(candidate.clone());
+
+            if let Some(hosts) = &self.hosts {
+                // hosts file entries are stored without the trailing root label
+                let mut host_name = candidate.clone();
+                host_name.set_fqdn(false);
+                if let Some(lookup) =
+                    hosts.lookup_static_host(&Query::query(host_name, record_type))
+                {
+                    return Ok(TracedLookup {
+                        records: lookup.records().to_vec(),
+                        source: ResolutionSource::HostsFile {
+                            name: candidate.to_string(),
+                        },
+                        tried,
+                    });
+                }
+            }
+
+            for (addr, resolver) in self.name_servers.iter() {
+                match resolver.lookup(candidate.clone(), record_type).await {
+                    Ok(lookup) => {
+                        return Ok(TracedLookup {
+                            records: lookup.records().to_vec(),
+                            source: ResolutionSource::NameServer {
+                                name: candidate.to_string(),
+                                addr: *addr,
+                            },
+                            tried,
+                        })
+                    }
+                    Err(e) => match e.kind() {
+                        ResolveErrorKind::NoRecordsFound { .. } => {
+                            // the name server answered, move on to the next candidate name
+                            negative_answer = Some(ResolutionSource::NameServer {
+                                name: candidate.to_string(),
+                                addr: *addr,
+                            });
+                            break;
+                        }
+                        _ => last_error = Some(e),
+                    },
+                }
+            }
+        }
+
+        if let Some(source) = negative_answer {
+            return Ok(TracedLookup {
+                records: Vec::new(),
+                source,
+                tried,
+            });
+        }
+        let tried = display_names(&tried);
+        match last_error {
+            Some(e) => bail!(
+                "failed to resolve '{name}' (tried {tried}): {error}",
+                error = match e.kind() {
+                    ResolveErrorKind::Message(msg) => msg.to_string(),
+                    _ => e.to_string(),
+                }
+            ),
+            None => bail!("failed to resolve '{name}' (tried {tried}): no name servers configured"),
+        }
+    }
+
+    /// Look up A records, falling back to AAAA
+    pub async fn lookup_ip(&self, name: &str) -> anyhow::Result<TracedLookup> {
+        match self.lookup(name, RecordType::A).await {
+            Ok(lookup) if !lookup.ips().is_empty() => Ok(lookup),
+            v4_result => match self.lookup(name, RecordType::AAAA).await {
+                Ok(lookup) if !lookup.ips().is_empty() => Ok(lookup),
+                Ok(lookup) => bail!(
+                    "failed to resolve '{name}' (tried {}): no records found",
+                    display_names(&lookup.tried)
+                ),
+                Err(e) => v4_result.and(Err(e)),
+            },
+        }
+    }
+}
+
+/// Display names as a comma separated list
+pub fn display_names(names: &[Name]) -> String {
+    names
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[test]
+fn test_candidate_names_search_order() -> anyhow::Result<()> {
+    use std::str::FromStr;
+    let config =
+        ResolverConfig::from_parts(None, vec![Name::from_str("corp.example.com.")?], vec![]);
+    let resolver = TracingResolver::new(config, ResolverOpts::default());
+    // below ndots, search domains are tried first
+    assert_eq!(
+        display_names(&resolver.candidate_names("intranet")?),
+        "intranet.corp.example.com., intranet."
+    );
+    // above ndots, the name is tried as-is first
+    assert_eq!(
+        display_names(&resolver.candidate_names("www.example.org")?),
+        "www.example.org., www.example.org.corp.example.com."
+    );
+    // fully qualified names skip the search list
+    assert_eq!(
+        display_names(&resolver.candidate_names("www.example.org.")?),
+        "www.example.org."
+    );
+    Ok(())
+}
@@ -1,41 +1,52 @@
 use crate::*;
 use trust_dns_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
-    error::ResolveErrorKind,
-    proto::rr::RecordType,
-    TokioAsyncResolver,
+    proto::rr::{
+        rdata::{
+            caa::{Property, Value},
+            sshfp, svcb, tlsa,
+        },
+        RData, RecordType,
+    },
 };
 
+mod bench;
+
 #[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Option<Command>,
     /// hosts to query
-    #[clap(required = true)]
+    #[clap(required_unless_present = "system_config")]
     fqdns: Vec<String>,
     /// record type to query
     #[clap(short = 't', long = "type", default_value = "A")]
     record_type: String,
     #[clap(short = '@', long)]
     name_server: Option<Vec<String>>,
+    /// print the parsed system resolver configuration (resolv.conf)
+    #[clap(long)]
+    system_config: bool,
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Command {
+    /// Measure and rank the latency and reliability of name servers
+    Bench(bench::Opts),
 }
 
 pub async fn main(opts: &Opts) -> Result<()> {
-    let r_config = if let Some(ns) = &opts.name_server {
-        let mut cfg = ResolverConfig::new();
-        for ns in ns.iter() {
-            let addr = if ns.contains(':') {
-                ns.to_owned()
-            } else {
-                format!("{}:53", ns)
-            }
-            .parse::<SocketAddr>()
-            .map_err(|e| anyhow!("Failed to parse SocketAddr for name server '{ns}': {e}"))?;
-            cfg.add_name_server(NameServerConfig::new(addr, Protocol::Udp))
-        }
-        cfg
-    } else {
-        ResolverConfig::default()
-    };
-    let r_opts = ResolverOpts::default();
+    if let Some(cmd) = &opts.cmd {
+        return match cmd {
+            Command::Bench(opts) => bench::main(opts).await,
+        };
+    }
+    if opts.system_config {
+        return print_system_config();
+    }
+
+    let (r_config, r_opts) = resolver_config(opts)?;
 
     let record_type = RecordType::from_str(&opts.record_type.to_uppercase()).map_err(|e| {
         anyhow!(
@@ -44,25 +55,24 @@ pub async fn main(opts: &Opts) -> Result<()> {
         )
     })?;
 
-    let resolver = TokioAsyncResolver::tokio(r_config.clone(), r_opts);
+    let resolver = TracingResolver::new(r_config, r_opts);
 
     for fqdn in opts.fqdns.iter() {
         println!("Query:\t\tFQDN: {};\tType: {};", fqdn, opts.record_type);
 
-        let lookup = resolver.lookup(fqdn, record_type).await;
+        let lookup = resolver
+            .lookup(fqdn, record_type)
+            .await
+            .map_err(|e| anyhow!("failed to query DNS: {e}"))?;
+        if lookup.tried.len() > 1 {
+            println!("Tried:\t\t{}", display_names(&lookup.tried));
+        }
 
-        let records = match lookup {
-            Ok(lookup) => lookup.records().to_owned(),
-            Err(e) => match e.kind() {
-                ResolveErrorKind::NoRecordsFound { .. } => {
-                    println!("Response:\nx\tNo records found!");
-                    continue;
-                }
-                e => {
-                    return Err(anyhow!("failed to query DNS: {e}"));
-                }
-            },
-        };
+        let records = lookup.records;
+        if records.is_empty() {
+            println!("Response:\t{}\nx\tNo records found!", lookup.source);
+            continue;
+        }
 
         let mut response_spacing = [0; 2];
         for response in records.iter() {
@@ -81,18 +91,18 @@ pub async fn main(opts: &Opts) -> Result<()> {
         }
 
         // print response
-        println!("Response:\t{}", r_config.name_servers()[0]);
+        println!("Response:\t{}", lookup.source);
         // record header
         match record_type {
             RecordType::MX => println!(
                 "[Query{}FQDN{}TTL\tDIR\tTYPE\tPRIO\tVALUE\t\t]",
-                " ".repeat(response_spacing[0] - 5),
+                " ".repeat(response_spacing[0].saturating_sub(5)),
                 " ".repeat(response_spacing[1])
             ),
             _ => println!(
                 "[Query{}FQDN{}\tTTL\tDIR\tTYPE\tVALUE\t\t]",
-                " ".repeat(response_spacing[0] - 5),
-                " ".repeat(response_spacing[1] - 4)
+                " ".repeat(response_spacing[0].saturating_sub(5)),
+                " ".repeat(response_spacing[1].saturating_sub(4))
             ),
         }
 
@@ -104,13 +114,346 @@ pub async fn main(opts: &Opts) -> Result<()> {
                 str[0],
                 " ".repeat(response_spacing[1] - str[0].len())
             );
+            let fields = response.data().and_then(describe_rdata);
+            if fields.is_some() {
+                // the named fields below replace the raw value columns
+                str.truncate(4);
+            }
             println!(
                 "{}{} {}",
                 response.name(),
                 " ".repeat(response_spacing[0] - response.name().len()),
                 str.join("\t")
             );
+            for (name, value) in fields.into_iter().flatten() {
+                println!("\t- {name}:\t{value}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the resolver configuration as parsed from the system
+fn print_system_config() -> Result<()> {
+    let resolver = TracingResolver::from_system_conf()?;
+    let (config, r_opts) = (resolver.config(), resolver.opts());
+
+    let mut name_servers = Vec::<String>::new();
+    for ns in config.name_servers() {
+        let ns = ns.socket_addr.to_string();
+        if !name_servers.contains(&ns) {
+            name_servers.push(ns);
         }
     }
+
+    println!("Name servers:");
+    for ns in name_servers.iter() {
+        println!("- {ns}");
+    }
+    println!(
+        "Domain:           {}",
+        config
+            .domain()
+            .map(|d| d.to_string())
+            .unwrap_or("<none>".to_string())
+    );
+    match config.search() {
+        [] => println!("Search domains:   <none>"),
+        search => println!("Search domains:   {}", display_names(search)),
+    }
+    println!("ndots:            {}", r_opts.ndots);
+    println!("Timeout:          {:?}", r_opts.timeout);
+    println!("Attempts:         {}", r_opts.attempts);
+    println!("Rotate:           {}", r_opts.rotate);
+    println!("Use hosts file:   {}", r_opts.use_hosts_file);
+    Ok(())
+}
+
+/// The resolver to query with, the hosts file is skipped when name servers are given
+/// so the answer always comes from them
+fn resolver_config(opts: &Opts) -> Result<(ResolverConfig, ResolverOpts)> {
+    let mut r_opts = ResolverOpts::default();
+    let r_config = if let Some(ns) = &opts.name_server {
+        let mut cfg = ResolverConfig::new();
+        for ns in ns.iter() {
+            cfg.add_name_server(NameServerConfig::new(parse_name_server(ns)?, Protocol::Udp))
+        }
+        r_opts.use_hosts_file = false;
+        cfg
+    } else {
+        ResolverConfig::default()
+    };
+    Ok((r_config, r_opts))
+}
+
+/// Parse a name server address, defaulting to port 53 if none is given
+fn parse_name_server(ns: &str) -> Result<SocketAddr> {
+    if ns.contains(':') {
+        ns.to_owned()
+    } else {
+        format!("{}:53", ns)
+    }
+    .parse::<SocketAddr>()
+    .map_err(|e| anyhow!("Failed to parse SocketAddr for name server '{ns}': {e}"))
+}
+
+/// Break down the record types whose presentation format is hard to read into named fields,
+/// returns `None` for record types that display fine as-is
+fn describe_rdata(data: &RData) -> Option<Vec<(&'static str, String)>> {
+    let fields = match data {
+        RData::SRV(srv) => vec![
+            ("target", srv.target().to_string()),
+            ("port", srv.port().to_string()),
+            (
+                "priority",
+                format!("{} (lowest value is tried first)", srv.priority()),
+            ),
+            (
+                "weight",
+                format!(
+                    "{} (relative share among records of equal priority)",
+                    srv.weight()
+                ),
+            ),
+        ],
+        RData::CAA(caa) => {
+            let mut fields = vec![(
+                "flags",
+                match caa.issuer_critical() {
+                    true => "128 (critical, CAs must understand this tag)".to_string(),
+                    false => "0".to_string(),
+                },
+            )];
+            fields.push((
+                "tag",
+                format!(
+                    "{} ({})",
+                    caa.tag().as_str(),
+                    match caa.tag() {
+                        Property::Issue => "CA allowed to issue certificates",
+                        Property::IssueWild => "CA allowed to issue wildcard certificates",
+                        Property::Iodef => "where CAs report policy violations",
+                        Property::Unknown(_) => "unknown tag",
+                    }
+                ),
+            ));
+            match caa.value() {
+                Value::Issuer(None, _) => {
+                    fields.push(("issuer", "<none> (no CA may issue)".to_string()))
+                }
+                Value::Issuer(Some(name), _) => fields.push(("issuer", name.to_string())),
+                Value::Url(url) => fields.push(("url", url.to_string())),
+                Value::Unknown(bytes) => {
+                    fields.push(("value", String::from_utf8_lossy(bytes).to_string()))
+                }
+            }
+            if let Value::Issuer(_, key_values) = caa.value() {
+                for kv in key_values.iter() {
+                    fields.push(("parameter", format!("{}={}", kv.key(), kv.value())));
+                }
+            }
+            fields
+        }
+        RData::TLSA(tlsa) => vec![
+            (
+                "usage",
+                format!(
+                    "{} ({})",
+                    u8::from(tlsa.cert_usage()),
+                    match tlsa.cert_usage() {
+                        tlsa::CertUsage::CA => "PKIX-TA, CA constraint",
+                        tlsa::CertUsage::Service => "PKIX-EE, service certificate constraint",
+                        tlsa::CertUsage::TrustAnchor => "DANE-TA, trust anchor assertion",
+                        tlsa::CertUsage::DomainIssued => "DANE-EE, domain-issued certificate",
+                        tlsa::CertUsage::Private => "private use",
+                        tlsa::CertUsage::Unassigned(_) => "unassigned",
+                    }
+                ),
+            ),
+            (
+                "selector",
+                format!(
+                    "{} ({})",
+                    u8::from(tlsa.selector()),
+                    match tlsa.selector() {
+                        tlsa::Selector::Full => "full certificate",
+                        tlsa::Selector::Spki => "subject public key info",
+                        tlsa::Selector::Private => "private use",
+                        tlsa::Selector::Unassigned(_) => "unassigned",
+                    }
+                ),
+            ),
+            (
+                "matching type",
+                format!(
+                    "{} ({})",
+                    u8::from(tlsa.matching()),
+                    match tlsa.matching() {
+                        tlsa::Matching::Raw => "exact match",
+                        tlsa::Matching::Sha256 => "SHA-256 hash",
+                        tlsa::Matching::Sha512 => "SHA-512 hash",
+                        tlsa::Matching::Private => "private use",
+                        tlsa::Matching::Unassigned(_) => "unassigned",
+                    }
+                ),
+            ),
+            ("data", display_hex(tlsa.cert_data())),
+        ],
+        RData::SSHFP(sshfp) => vec![
+            (
+                "algorithm",
+                format!(
+                    "{} ({})",
+                    u8::from(sshfp.algorithm()),
+                    match sshfp.algorithm() {
+                        sshfp::Algorithm::Reserved => "reserved",
+                        sshfp::Algorithm::RSA => "RSA",
+                        sshfp::Algorithm::DSA => "DSA",
+                        sshfp::Algorithm::ECDSA => "ECDSA",
+                        sshfp::Algorithm::Ed25519 => "Ed25519",
+                        sshfp::Algorithm::Ed448 => "Ed448",
+                        sshfp::Algorithm::Unassigned(_) => "unassigned",
+                    }
+                ),
+            ),
+            (
+                "fingerprint type",
+                format!(
+                    "{} ({})",
+                    u8::from(sshfp.fingerprint_type()),
+                    match sshfp.fingerprint_type() {
+                        sshfp::FingerprintType::Reserved => "reserved",
+                        sshfp::FingerprintType::SHA1 => "SHA-1",
+                        sshfp::FingerprintType::SHA256 => "SHA-256",
+                        sshfp::FingerprintType::Unassigned(_) => "unassigned",
+                    }
+                ),
+            ),
+            ("fingerprint", display_hex(sshfp.fingerprint())),
+        ],
+        RData::SVCB(svcb) => describe_svcb(svcb),
+        RData::HTTPS(https) => describe_svcb(https),
+        RData::NAPTR(naptr) => vec![
+            (
+                "order",
+                format!("{} (lowest value is processed first)", naptr.order()),
+            ),
+            (
+                "preference",
+                format!(
+                    "{} (lowest value is preferred among equal order)",
+                    naptr.preference()
+                ),
+            ),
+            (
+                "flags",
+                format!(
+                    "\"{}\"{}",
+                    String::from_utf8_lossy(naptr.flags()),
+                    match naptr.flags().to_ascii_uppercase().as_slice() {
+                        b"S" => " (next lookup is SRV)",
+                        b"A" => " (next lookup is A/AAAA)",
+                        b"U" => " (terminal, regexp produces a URI)",
+                        b"P" => " (protocol specific)",
+                        b"" => " (non-terminal, continue with replacement)",
+                        _ => "",
+                    }
+                ),
+            ),
+            (
+                "services",
+                format!("\"{}\"", String::from_utf8_lossy(naptr.services())),
+            ),
+            (
+                "regexp",
+                format!("\"{}\"", String::from_utf8_lossy(naptr.regexp())),
+            ),
+            ("replacement", naptr.replacement().to_string()),
+        ],
+        _ => return None,
+    };
+    Some(fields)
+}
+
+/// Break down the fields shared by SVCB and HTTPS records
+fn describe_svcb(svcb: &svcb::SVCB) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        (
+            "priority",
+            match svcb.svc_priority() {
+                0 => "0 (alias mode, see target)".to_string(),
+                p => format!("{p} (service mode, lowest value is preferred)"),
+            },
+        ),
+        (
+            "target",
+            match svcb.target_name().is_root() {
+                true => ". (same as owner name)".to_string(),
+                false => svcb.target_name().to_string(),
+            },
+        ),
+    ];
+    for (key, value) in svcb.svc_params().iter() {
+        let value = match value {
+            svcb::SvcParamValue::Mandatory(m) => {
+                m.0.iter()
+                    .map(|k| k.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+            svcb::SvcParamValue::Alpn(alpn) => alpn.0.join(","),
+            svcb::SvcParamValue::NoDefaultAlpn => "(default ALPN not supported)".to_string(),
+            svcb::SvcParamValue::Port(port) => port.to_string(),
+            svcb::SvcParamValue::Ipv4Hint(hint) => hint
+                .0
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            svcb::SvcParamValue::Ipv6Hint(hint) => hint
+                .0
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            svcb::SvcParamValue::EchConfig(ech) => {
+                format!("{ech} ({} byte Encrypted ClientHello config)", ech.0.len())
+            }
+            svcb::SvcParamValue::Unknown(unknown) => display_hex(&unknown.0),
+        };
+        fields.push(match key {
+            svcb::SvcParamKey::Mandatory => ("mandatory", value),
+            svcb::SvcParamKey::Alpn => ("alpn", value),
+            svcb::SvcParamKey::NoDefaultAlpn => ("no-default-alpn", value),
+            svcb::SvcParamKey::Port => ("port", value),
+            svcb::SvcParamKey::Ipv4Hint => ("ipv4hint", value),
+            svcb::SvcParamKey::EchConfig => ("ech", value),
+            svcb::SvcParamKey::Ipv6Hint => ("ipv6hint", value),
+            key => ("param", format!("{key}={value}")),
+        });
+    }
+    fields
+}
+
+/// Display bytes as a lowercase hex string
+fn display_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[test]
+fn test_resolver_config_hosts_file() -> Result<()> {
+    let (_, r_opts) = resolver_config(&Opts::try_parse_from(["dns", "localhost"])?)?;
+    assert!(r_opts.use_hosts_file);
+    let (config, r_opts) = resolver_config(&Opts::try_parse_from([
+        "dns",
+        "-@",
+        "192.0.2.1",
+        "localhost",
+    ])?)?;
+    assert!(!r_opts.use_hosts_file);
+    assert_eq!(
+        config.name_servers()[0].socket_addr,
+        "192.0.2.1:53".parse::<SocketAddr>()?
+    );
     Ok(())
 }
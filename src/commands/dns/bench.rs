@@ -0,0 +1,217 @@
+use crate::*;
+use std::time::{Duration, Instant};
+use trust_dns_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::ResolveErrorKind,
+    proto::rr::RecordType,
+    TokioAsyncResolver,
+};
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// name server(s) to benchmark
+    #[clap(short = '@', long, required = true)]
+    name_server: Vec<String>,
+    /// file of names to query, one per line
+    #[clap(long)]
+    names: PathBuf,
+    /// number of times each name is queried against each name server
+    #[clap(long, default_value = "5")]
+    rounds: usize,
+    /// record type to query
+    #[clap(short = 't', long = "type", default_value = "A")]
+    record_type: String,
+    /// query timeout in milliseconds
+    #[clap(long, default_value = "2000")]
+    timeout: u64,
+}
+
+pub async fn main(opts: &Opts) -> Result<()> {
+    ensure!(opts.rounds > 0, "rounds must be at least 1");
+
+    let names = std::fs::read_to_string(&opts.names)
+        .map_err(|e| anyhow!("Unable to read file '{:?}': {}", &opts.names, e))?
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+    ensure!(!names.is_empty(), "no names found in '{:?}'", &opts.names);
+
+    let record_type = RecordType::from_str(&opts.record_type.to_uppercase()).map_err(|e| {
+        anyhow!(
+            "Failed to parse record type from '{}': {e}",
+            opts.record_type
+        )
+    })?;
+
+    let mut r_opts = ResolverOpts::default();
+    // every query must reach the name server for the timings to mean anything
+    r_opts.cache_size = 0;
+    r_opts.attempts = 1;
+    r_opts.timeout = Duration::from_millis(opts.timeout);
+
+    let mut resolvers = Vec::with_capacity(opts.name_server.len());
+    for ns in opts.name_server.iter() {
+        let mut cfg = ResolverConfig::new();
+        cfg.add_name_server(NameServerConfig::new(
+            super::parse_name_server(ns)?,
+            Protocol::Udp,
+        ));
+        resolvers.push((ns.to_owned(), TokioAsyncResolver::tokio(cfg, r_opts)));
+    }
+
+    println!(
+        "Benchmarking {} name server(s) with {} name(s) over {} round(s)",
+        resolvers.len(),
+        names.len(),
+        opts.rounds
+    );
+
+    let mut results = resolvers
+        .iter()
+        .map(|(ns, _)| BenchResult::new(ns.to_owned()))
+        .collect::<Vec<_>>();
+
+    // the first round hits each name server's cache cold, later rounds should be answered warm
+    for round in 0..opts.rounds {
+        for name in names.iter() {
+            let queries = resolvers.iter().map(|(_, resolver)| async move {
+                let start = Instant::now();
+                let result = resolver.lookup(name.as_str(), record_type).await;
+                let elapsed = start.elapsed();
+                match result {
+                    Ok(_) => Ok(elapsed),
+                    Err(e) => match e.kind() {
+                        // the name server answered, the name just has no records
+                        ResolveErrorKind::NoRecordsFound { .. } => Ok(elapsed),
+                        _ => Err(e),
+                    },
+                }
+            });
+            for (result, outcome) in results
+                .iter_mut()
+                .zip(futures::future::join_all(queries).await)
+            {
+                match outcome {
+                    Ok(elapsed) if round == 0 => result.cold.push(elapsed),
+                    Ok(elapsed) => result.warm.push(elapsed),
+                    Err(e) => {
+                        debug!(target: "dns", "{} failed to resolve '{name}': {e}", result.name_server);
+                        result.failures += 1
+                    }
+                }
+            }
+        }
+    }
+
+    results.sort_by(|a, b| {
+        a.failure_rate()
+            .total_cmp(&b.failure_rate())
+            .then_with(|| a.percentile(50).cmp(&b.percentile(50)))
+    });
+
+    let ns_width = results
+        .iter()
+        .map(|r| r.name_server.len())
+        .max()
+        .unwrap_or_default()
+        .max("NAME SERVER".len());
+    println!();
+    println!(
+        "RANK  {: <ns_width$}  {: >7}  {: >7}  {: >9}  {: >9}  {: >9}  {: >9}  {: >9}",
+        "NAME SERVER", "QUERIES", "FAILED", "COLD AVG", "WARM AVG", "P50", "P95", "P99"
+    );
+    for (idx, result) in results.iter().enumerate() {
+        println!(
+            "{: >4}  {: <ns_width$}  {: >7}  {: >6.1}%  {: >9}  {: >9}  {: >9}  {: >9}  {: >9}",
+            idx + 1,
+            result.name_server,
+            result.queries(),
+            result.failure_rate() * 100.0,
+            display_duration(average(&result.cold)),
+            display_duration(average(&result.warm)),
+            display_duration(result.percentile(50)),
+            display_duration(result.percentile(95)),
+            display_duration(result.percentile(99)),
+        );
+    }
+
+    Ok(())
+}
+
+/// Timings collected for a single name server
+struct BenchResult {
+    name_server: String,
+    /// Successful query durations from the first round
+    cold: Vec<Duration>,
+    /// Successful query durations from every following round
+    warm: Vec<Duration>,
+    failures: usize,
+}
+
+impl BenchResult {
+    fn new(name_server: String) -> Self {
+        Self {
+            name_server,
+            cold: Vec::new(),
+            warm: Vec::new(),
+            failures: 0,
+        }
+    }
+
+    fn queries(&self) -> usize {
+        self.cold.len() + self.warm.len() + self.failures
+    }
+
+    fn failure_rate(&self) -> f64 {
+        match self.queries() {
+            0 => 0.0,
+            queries => self.failures as f64 / queries as f64,
+        }
+    }
+
+    /// Percentile over every successful query, cold and warm
+    fn percentile(&self, pct: usize) -> Option<Duration> {
+        let mut samples = self
+            .cold
+            .iter()
+            .chain(self.warm.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        samples.sort();
+        percentile(&samples, pct)
+    }
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], pct: usize) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+fn average(samples: &[Duration]) -> Option<Duration> {
+    match samples.len() {
+        0 => None,
+        len => Some(samples.iter().sum::<Duration>() / len as u32),
+    }
+}
+
+fn display_duration(d: Option<Duration>) -> String {
+    match d {
+        Some(d) => format!("{:.1}ms", d.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+#[test]
+fn test_percentile_nearest_rank() {
+    let samples = (1..=10).map(Duration::from_millis).collect::<Vec<_>>();
+    assert_eq!(percentile(&samples, 50), Some(Duration::from_millis(5)));
+    assert_eq!(percentile(&samples, 95), Some(Duration::from_millis(10)));
+    assert_eq!(percentile(&samples, 0), Some(Duration::from_millis(1)));
+    assert_eq!(percentile(&[], 50), None);
+}
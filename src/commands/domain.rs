@@ -3,13 +3,31 @@ use std::sync::atomic::AtomicBool;
 use tokio::sync::Mutex;
 use trust_dns_resolver::TokioAsyncResolver;
 
+mod enumerate;
+
 #[derive(clap::Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Option<Command>,
     #[clap(required = true)]
     domains: Vec<Domain>,
 }
 
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Command {
+    /// Discover subdomains by resolving candidates from a wordlist
+    #[clap(name = "enum")]
+    Enumerate(enumerate::Opts),
+}
+
 pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
+    if let Some(cmd) = &mut opts.cmd {
+        return match cmd {
+            Command::Enumerate(opts) => enumerate::main(opts).await,
+        };
+    }
+
     let validator = Arc::new(DomainValidator::new()?);
 
     let mut tasks = Vec::with_capacity(opts.domains.len());
@@ -126,8 +144,7 @@ impl std::fmt::Display for DomainReport {
 
 impl DomainReport {
     pub async fn generate(domain: Domain) -> anyhow::Result<Self> {
-        let dns_client = TokioAsyncResolver::tokio_from_system_conf()
-            .map_err(|e| anyhow!("failed to create DNS resolver: {e}"))?;
+        let dns_client = system_resolver()?;
         let domain_str = domain.as_str();
 
         let ns = dns_client
@@ -175,7 +192,7 @@ impl DomainReport {
             .map(|mut v| match v.len() {
                 0 => None,
                 _ => {
-                    v.sort_by(|a, b| a.priority.cmp(&b.priority));
+                    v.sort_by_key(|a| a.priority);
                     Some(v)
                 }
             })?;
@@ -236,6 +253,12 @@ impl DomainReport {
     }
 }
 
+/// DNS resolver configured from the system, shared by the domain reports and subdomain enumeration
+fn system_resolver() -> anyhow::Result<TokioAsyncResolver> {
+    TokioAsyncResolver::tokio_from_system_conf()
+        .map_err(|e| anyhow!("failed to create DNS resolver: {e}"))
+}
+
 pub struct DomainValidator {
     refreshing: AtomicBool,
     has_refreshed: AtomicBool,
@@ -0,0 +1,193 @@
+use crate::*;
+use futures::StreamExt;
+use std::collections::BTreeSet;
+use trust_dns_resolver::{
+    error::ResolveErrorKind,
+    proto::rr::{RData, RecordType},
+    TokioAsyncResolver,
+};
+
+#[derive(clap::Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// domain to discover subdomains of
+    #[clap(required = true)]
+    domain: Domain,
+    /// file of subdomain labels to try, one per line
+    #[clap(short, long)]
+    wordlist: PathBuf,
+    /// maximum number of names resolved at once
+    #[clap(short, long, default_value = "50")]
+    concurrency: usize,
+    /// number of random labels queried to detect wildcard DNS
+    #[clap(long, default_value = "3")]
+    wildcard_probes: usize,
+}
+
+pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
+    ensure!(opts.concurrency > 0, "concurrency must be at least 1");
+
+    let labels = std::fs::read_to_string(&opts.wordlist)
+        .map_err(|e| anyhow!("Unable to read file '{:?}': {}", &opts.wordlist, e))?
+        .lines()
+        .map(|l| l.trim().trim_end_matches('.').to_lowercase())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect::<BTreeSet<_>>();
+    ensure!(
+        !labels.is_empty(),
+        "no labels found in '{:?}'",
+        &opts.wordlist
+    );
+
+    let resolver = super::system_resolver()?;
+    let domain = &opts.domain;
+
+    // a wildcard record answers for any label, so random ones reveal what to filter out
+    let mut wildcard = BTreeSet::new();
+    let mut failed_probes = 0;
+    for _ in 0..opts.wildcard_probes {
+        let label = rand::thread_rng()
+            .sample_iter(rand::distributions::Alphanumeric)
+            .take(16)
+            .map(|c| (c as char).to_ascii_lowercase())
+            .collect::<String>();
+        match resolve(&resolver, fqdn(&label, domain)).await {
+            Ok(Some(answer)) => wildcard.extend(answer.targets()),
+            Ok(None) => {}
+            Err(e) => {
+                debug!(target: "domain", "{e}");
+                failed_probes += 1;
+            }
+        }
+    }
+    if failed_probes > 0 {
+        warn!(
+            target: "domain",
+            "{failed_probes} of {} wildcard probe(s) failed to resolve, wildcard answers may be reported",
+            opts.wildcard_probes
+        );
+    }
+    if !wildcard.is_empty() {
+        println!(
+            "Wildcard DNS detected for {domain}, ignoring answers only pointing to: {}",
+            wildcard.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    let mut results = futures::stream::iter(labels.iter())
+        .map(|label| {
+            let resolver = &resolver;
+            async move {
+                let name = fqdn(label, domain);
+                let answer = resolve(resolver, name.clone()).await;
+                (name, answer)
+            }
+        })
+        .buffer_unordered(opts.concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut live = Vec::new();
+    let mut failed = 0;
+    for (name, answer) in results {
+        match answer {
+            Ok(Some(answer)) => {
+                if !wildcard.is_empty() && answer.targets().is_subset(&wildcard) {
+                    continue;
+                }
+                live.push((name, answer))
+            }
+            Ok(None) => {}
+            Err(e) => {
+                debug!(target: "domain", "{e}");
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "Found {} live subdomain(s) of {domain} ({} checked)",
+        live.len(),
+        labels.len()
+    );
+    for (name, answer) in live.iter() {
+        println!("- {name}");
+        for cname in answer.cnames.iter() {
+            println!("\tCNAME\t{cname}");
+        }
+        for ip in answer.ips.iter() {
+            match ip {
+                IpAddr::V4(_) => println!("\tA\t{ip}"),
+                IpAddr::V6(_) => println!("\tAAAA\t{ip}"),
+            }
+        }
+    }
+    if failed > 0 {
+        warn!(target: "domain", "{failed} name(s) failed to resolve and were skipped");
+    }
+
+    Ok(())
+}
+
+/// The fully qualified name of a label under the domain, the trailing dot keeps the
+/// resolver from appending search domains to it
+fn fqdn(label: &str, domain: &Domain) -> String {
+    format!("{label}.{}.", domain.as_str().trim_end_matches('.'))
+}
+
+/// The records a live name resolved to
+struct Answer {
+    cnames: Vec<String>,
+    ips: Vec<IpAddr>,
+}
+
+impl Answer {
+    /// Everything the name points to, used to compare against wildcard answers
+    fn targets(&self) -> BTreeSet<String> {
+        self.cnames
+            .iter()
+            .cloned()
+            .chain(self.ips.iter().map(|ip| ip.to_string()))
+            .collect()
+    }
+}
+
+/// Resolve the A and AAAA records of a name, returns `None` if the name doesn't exist
+async fn resolve(resolver: &TokioAsyncResolver, name: String) -> anyhow::Result<Option<Answer>> {
+    let mut answer = Answer {
+        cnames: Vec::new(),
+        ips: Vec::new(),
+    };
+    for record_type in [RecordType::A, RecordType::AAAA] {
+        let lookup = match resolver.lookup(name.as_str(), record_type).await {
+            Ok(lookup) => lookup,
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => continue,
+                _ => bail!("failed to resolve '{name}': {e}"),
+            },
+        };
+        for record in lookup.records() {
+            match record.data() {
+                Some(RData::CNAME(cname)) => {
+                    let cname = cname.to_string();
+                    if !answer.cnames.contains(&cname) {
+                        answer.cnames.push(cname);
+                    }
+                }
+                Some(RData::A(a)) => answer.ips.push(IpAddr::V4(a.0)),
+                Some(RData::AAAA(aaaa)) => answer.ips.push(IpAddr::V6(aaaa.0)),
+                _ => {}
+            }
+        }
+    }
+    Ok(match answer.cnames.is_empty() && answer.ips.is_empty() {
+        true => None,
+        false => Some(answer),
+    })
+}
+
+#[test]
+fn test_fqdn() {
+    let domain = Domain::new("example.com".to_string()).unwrap();
+    assert_eq!(fqdn("www", &domain), "www.example.com.");
+}
@@ -25,6 +25,12 @@ pub struct Opts {
     /// only show open ports
     #[clap(short, long)]
     only_open: bool,
+    /// show the whole banner of open ports as a hexdump
+    #[clap(long, conflicts_with = "escape")]
+    hex: bool,
+    /// show the whole banner of open ports with control characters and non-ASCII bytes escaped
+    #[clap(long)]
+    escape: bool,
 }
 
 pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
@@ -97,7 +103,14 @@ pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
         };
         let port_task_results =
             futures::future::join_all(task_list.into_iter().map(tokio::spawn)).await;
-        println!("Results for host '{host}' ({ip})");
+        match &host.source {
+            Some(
+                source @ (ResolutionSource::HostsFile { .. } | ResolutionSource::NameServer { .. }),
+            ) => {
+                println!("Results for host '{host}' ({ip}, via {source})")
+            }
+            _ => println!("Results for host '{host}' ({ip})"),
+        }
         for (idx, port_task_result) in port_task_results.iter().enumerate() {
             let port = ports
                 .get(idx)
@@ -106,9 +119,24 @@ pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
                 "\t{port: >5}: {}",
                 match port_task_result {
                     Err(e) => format!("☠️ Thread error\t({e})"),
-                    Ok(Ok((optional_line, duration))) => match optional_line {
-                        Some(received_line) =>
-                            format!("✅ Open ({duration:?})\t\t\"{received_line}\""),
+                    Ok(Ok((optional_banner, duration))) => match optional_banner {
+                        Some(banner) if opts.hex => format!(
+                            "✅ Open ({duration:?})\n{}",
+                            utils::hexdump(banner, 0)
+                                .lines()
+                                .map(|line| format!("\t       {line}"))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        ),
+                        Some(banner) if opts.escape => format!(
+                            "✅ Open ({duration:?})\t\t\"{}\"",
+                            utils::escape_bytes(banner)
+                        ),
+                        Some(banner) => match String::from_utf8_lossy(banner).lines().next() {
+                            Some(received_line) =>
+                                format!("✅ Open ({duration:?})\t\t\"{received_line}\""),
+                            None => format!("✅ Open ({duration:?})"),
+                        },
                         None => format!("✅ Open ({duration:?})"),
                     },
                     Ok(Err(e)) => {
@@ -139,13 +167,13 @@ pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Test if a port is open on a given IP, optionally return first line of connection response if received within 500ms
+/// Test if a port is open on a given IP, optionally return the connection response if received within the read timeout
 async fn test_port(
     ip: IpAddr,
     port: u16,
     timeout: u64,
     read_timeout: u64,
-) -> std::io::Result<(Option<String>, std::time::Duration)> {
+) -> std::io::Result<(Option<Vec<u8>>, std::time::Duration)> {
     let start_time = std::time::Instant::now();
     let mut stream = match tokio::time::timeout(
         tokio::time::Duration::from_millis(timeout),
@@ -159,7 +187,7 @@ async fn test_port(
     let connect_duration = start_time.elapsed();
 
     let mut buf = [0; 1024];
-    // return first line of response or None if no response
+    // return the response or None if no response
     match tokio::time::timeout(
         tokio::time::Duration::from_millis(read_timeout),
         stream.read(&mut buf),
@@ -168,16 +196,8 @@ async fn test_port(
     {
         // read timed out, return None
         Err(_) => Ok((None, connect_duration)),
-        // if read is successful, return the first line
+        // if read is successful, return the response
         // else, return the error
-        Ok(v) => v.map(|n| {
-            (
-                String::from_utf8_lossy(&buf[0..n])
-                    .lines()
-                    .next()
-                    .map(|s| s.to_string()),
-                start_time.elapsed(),
-            )
-        }),
+        Ok(v) => v.map(|n| ((n > 0).then(|| buf[0..n].to_vec()), start_time.elapsed())),
     }
 }
@@ -1,86 +1,959 @@
 use crate::*;
+use futures::StreamExt;
 use openssl::{
-    ssl::{SslConnector, SslMethod},
+    base64,
+    hash::MessageDigest,
+    nid::Nid,
+    ocsp::{OcspCertId, OcspCertStatus, OcspFlag, OcspResponse, OcspResponseStatus},
+    pkey::{Id, PKeyRef, Public},
+    ssl::{
+        SslConnector, SslConnectorBuilder, SslFiletype, SslMethod, SslRef, SslStream,
+        SslVerifyMode, StatusType,
+    },
+    stack::{Stack, StackRef},
     string::OpensslString,
+    x509::{
+        store::{X509Store, X509StoreBuilder},
+        X509NameRef, X509Ref, X509StoreContext, X509VerifyResult, X509,
+    },
 };
-use std::net::TcpStream;
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::Mutex,
+    time::Duration,
+};
+
+mod check;
+mod diff;
+mod file;
+mod gen;
+mod scan;
+mod serve;
+mod starttls;
 
 #[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(group(clap::ArgGroup::new("cert_files").args(["file", "p12"]).multiple(true)))]
 pub struct Opts {
-    /// hosts to query
-    #[clap(required = true)]
+    #[clap(subcommand)]
+    cmd: Option<Command>,
+    /// hosts to query, as `host[:port]` or `scheme://host[:port]` (https, smtp, imap, pop3, ftp, ldap, xmpp, postgres)
+    #[clap(required_unless_present_any = ["file", "p12", "hosts_file"])]
     hosts: Vec<String>,
+    #[clap(flatten)]
+    batch: BatchOpts,
     /// allow insecure connections
     #[clap(short, long)]
     insecure: bool,
+    /// display every certificate sent by the server and how they link together
+    #[clap(short, long)]
+    chain: bool,
+    #[clap(flatten)]
+    connect: ConnectOpts,
+    #[clap(flatten)]
+    tls: TlsOpts,
+    /// read certificates from a PEM or DER file instead of a host, PEM files may hold several
+    #[clap(long, value_name = "PATH")]
+    file: Vec<PathBuf>,
+    /// read the certificates and private key of a PKCS#12 bundle
+    #[clap(long, value_name = "PATH")]
+    p12: Option<PathBuf>,
+    /// environment variable holding the password of the PKCS#12 bundle or private key
+    #[clap(long, value_name = "VAR")]
+    pass_env: Option<String>,
+    /// private key file (PEM or DER) to check against the certificates read from files
+    #[clap(long, value_name = "PATH", requires = "cert_files")]
+    check_key: Option<PathBuf>,
+    /// write each certificate the server sent to this directory
+    #[clap(long, value_name = "DIR")]
+    save_dir: Option<PathBuf>,
+    /// write saved certificates as DER instead of PEM
+    #[clap(long, requires = "save_dir")]
+    der: bool,
+    /// also write the whole chain to a single PEM file
+    #[clap(long, requires = "save_dir")]
+    fullchain: bool,
+}
+
+/// Options controlling where a connection goes and which name is presented
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectOpts {
+    /// connect to this address (`ip[:port]` or `host[:port]`) instead of the host, the port defaults to the host's
+    #[clap(long, value_name = "ADDR")]
+    connect: Option<String>,
+    /// server name to send (SNI) and verify the certificate against, defaults to the host
+    #[clap(long, value_name = "NAME", conflicts_with = "no_sni")]
+    sni: Option<String>,
+    /// don't send a server name (SNI)
+    #[clap(long)]
+    no_sni: bool,
+    /// ALPN protocols to offer, comma separated in order of preference (e.g. `h2,http/1.1`)
+    #[clap(long, value_name = "PROTOCOLS")]
+    alpn: Option<String>,
+    /// connect, STARTTLS and handshake timeout in milliseconds
+    #[clap(long, default_value = "5000")]
+    timeout: u64,
+}
+
+/// Options for checking many hosts at once
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BatchOpts {
+    /// file of additional hosts, one per line
+    #[clap(long, value_name = "PATH")]
+    hosts_file: Option<PathBuf>,
+    /// maximum number of hosts connected to at once
+    #[clap(long, default_value = "10")]
+    concurrency: usize,
+}
+
+impl BatchOpts {
+    /// The hosts given as arguments followed by those in the hosts file
+    fn hosts(&self, hosts: &[String]) -> Result<Vec<String>> {
+        ensure!(self.concurrency > 0, "concurrency must be at least 1");
+        let mut hosts = hosts.to_vec();
+        if let Some(path) = &self.hosts_file {
+            hosts.extend(
+                std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))?
+                    .lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(|l| l.to_string()),
+            );
+        }
+        Ok(hosts)
+    }
+}
+
+/// Options for verifying the server and authenticating to it
+#[derive(clap::Args, Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TlsOpts {
+    /// trust the CA certificates in this PEM file instead of the system trust store
+    #[clap(long, value_name = "PATH")]
+    ca_file: Option<PathBuf>,
+    /// trust the CA certificates in the PEM files of this directory instead of the system trust store
+    #[clap(long, value_name = "PATH")]
+    ca_dir: Option<PathBuf>,
+    /// client certificate (PEM, may include its chain) to authenticate with
+    #[clap(long, value_name = "PATH")]
+    cert: Option<PathBuf>,
+    /// private key (PEM) of the client certificate, defaults to the certificate file
+    #[clap(long, value_name = "PATH", requires = "cert")]
+    key: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Command {
+    /// Check certificate expiry with monitoring plugin exit codes
+    Check(check::Opts),
+    /// Enumerate accepted protocol versions, cipher suites and groups, flagging weak ones
+    Scan(scan::Opts),
+    /// Generate private CAs, certificates and CSRs for testing
+    Gen(gen::Opts),
+    /// Compare the certificates of two hosts, or of a host against a saved snapshot
+    Diff(diff::Opts),
+    /// Run a TLS server logging the handshake each client offers
+    Serve(serve::Opts),
 }
 
 pub async fn main(opts: &Opts) -> Result<()> {
+    if let Some(cmd) = &opts.cmd {
+        return match cmd {
+            Command::Check(opts) => check::main(opts).await,
+            Command::Scan(opts) => scan::main(opts).await,
+            Command::Gen(opts) => gen::main(opts).await,
+            Command::Diff(opts) => diff::main(opts).await,
+            Command::Serve(opts) => serve::main(opts).await,
+        };
+    }
+
+    let mut builder = connector_builder(opts.insecure, &opts.tls)?;
+    if opts.chain {
+        // the chain view reports verification itself, a broken chain is what it's for
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+    let ssl_connector = builder.build();
+    let store = trust_store(&opts.tls)?;
+    let hosts = opts.batch.hosts(&opts.hosts)?;
+    let total = hosts.len();
+
+    let mut failed = 0;
+    let mut results = connect_all(&ssl_connector, hosts, &opts.connect, opts.batch.concurrency);
+    let mut idx = 0;
+    while let Some((input_host, result)) = results.next().await {
+        if idx > 0 {
+            println!();
+        }
+        idx += 1;
+        let result = result.and_then(|(host, stream)| {
+            print_host(opts, &store, &host, &stream)?;
+            match &opts.save_dir {
+                Some(dir) => save_chain(opts, dir, &input_host, &stream),
+                None => Ok(()),
+            }
+        });
+        if let Err(e) = result {
+            println!("Host:     {input_host}");
+            println!("Error:    {e}");
+            failed += 1;
+        }
+    }
+
+    print_files(opts, &store)?;
+    ensure!(failed == 0, "{failed} of {total} host(s) failed");
+    Ok(())
+}
+
+/// Display the session and certificates of an established connection
+fn print_host(
+    opts: &Opts,
+    store: &X509Store,
+    host: &str,
+    stream: &SslStream<TcpStream>,
+) -> Result<()> {
+    let chain = stream
+        .ssl()
+        .peer_cert_chain()
+        .ok_or(anyhow!("failed to get peer certificate chain"))?;
+    let cert = chain
+        .get(0)
+        .ok_or(anyhow!("failed to get peer certificate"))?;
+
+    println!("Host:     {}", host);
+    if let Some(addr) = &opts.connect.connect {
+        println!("Address:  {addr}");
+    }
+    if opts.connect.no_sni {
+        println!("SNI:      <none>");
+    } else if let Some(sni) = &opts.connect.sni {
+        println!("SNI:      {sni}");
+    }
+    print_session(stream.ssl(), chain, store)?;
+    if !opts.chain {
+        print_cert(cert)
+    } else {
+        print_chain(chain, store)
+    }
+}
+
+/// Write the certificates of a connection to files named after the host and their position in the chain
+fn save_chain(
+    opts: &Opts,
+    dir: &Path,
+    input_host: &str,
+    stream: &SslStream<TcpStream>,
+) -> Result<()> {
+    let chain = stream
+        .ssl()
+        .peer_cert_chain()
+        .ok_or(anyhow!("failed to get peer certificate chain"))?;
+    std::fs::create_dir_all(dir).map_err(|e| anyhow!("failed to create {dir:?}: {e}"))?;
+
+    // keep the port so different services on one host don't overwrite each other
+    let name = input_host
+        .split_once("://")
+        .map(|(_, host)| host)
+        .unwrap_or(input_host)
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+            "_",
+        );
+    let write = |path: PathBuf, contents: Vec<u8>| -> Result<()> {
+        std::fs::write(&path, contents).map_err(|e| anyhow!("failed to write {path:?}: {e}"))?;
+        println!("Saved:        {}", path.display());
+        Ok(())
+    };
+
+    let mut fullchain = Vec::new();
+    for (idx, cert) in chain.iter().enumerate() {
+        let pem = cert.to_pem()?;
+        match opts.der {
+            true => write(dir.join(format!("{name}-{idx}.der")), cert.to_der()?)?,
+            false => write(dir.join(format!("{name}-{idx}.pem")), pem.clone())?,
+        }
+        fullchain.extend(pem);
+    }
+    if opts.fullchain {
+        write(dir.join(format!("{name}-fullchain.pem")), fullchain)?;
+    }
+    Ok(())
+}
+
+/// Connect to every host, at most `concurrency` at once, yielding the results in input order
+fn connect_all<'a>(
+    ssl_connector: &'a SslConnector,
+    hosts: Vec<String>,
+    connect_opts: &'a ConnectOpts,
+    concurrency: usize,
+) -> impl futures::Stream<Item = (String, Result<(String, SslStream<TcpStream>)>)> + 'a {
+    futures::stream::iter(hosts)
+        .map(move |input_host| async move {
+            let result = connect_async(ssl_connector, &input_host, connect_opts).await;
+            (input_host, result)
+        })
+        .buffered(concurrency)
+}
+
+/// Connect to a host without blocking the executor, giving up after the connect timeout
+async fn connect_async(
+    ssl_connector: &SslConnector,
+    input_host: &str,
+    connect_opts: &ConnectOpts,
+) -> Result<(String, SslStream<TcpStream>)> {
+    let ssl_connector = ssl_connector.clone();
+    let connect_opts = connect_opts.clone();
+    let host = input_host.to_string();
+    let timeout = Duration::from_millis(connect_opts.timeout);
+    // the handshake is blocking, so each connection gets its own thread
+    let task = tokio::task::spawn_blocking(move || connect(&ssl_connector, &host, &connect_opts));
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(anyhow!("connection task failed: {e}")),
+        Err(_) => Err(anyhow!("timed out after {}ms", timeout.as_millis())),
+    }
+}
+
+/// Display the certificates read from files, and whether the private keys match them
+fn print_files(opts: &Opts, store: &X509Store) -> Result<()> {
+    let password = file::password(opts.pass_env.as_deref())?;
+    let check_key = match &opts.check_key {
+        Some(path) => Some((path, file::read_key(path, password.as_deref())?)),
+        None => None,
+    };
+
+    let mut files = Vec::new();
+    for path in opts.file.iter() {
+        files.push((path, file::read_certs(path)?, None));
+    }
+    if let Some(path) = &opts.p12 {
+        let (certs, key) = file::read_p12(path, password.as_deref().unwrap_or_default())?;
+        files.push((path, certs, key));
+    }
+
+    let mut check_key_matched = false;
+    for (idx, (path, certs, key)) in files.iter().enumerate() {
+        if idx > 0 || !opts.hosts.is_empty() {
+            println!();
+        }
+        println!("File:     {}", path.display());
+        if opts.chain {
+            print_chain(certs, store)?;
+        } else {
+            for (cert_idx, cert) in certs.iter().enumerate() {
+                if certs.len() > 1 {
+                    println!();
+                    println!("[{cert_idx}]");
+                }
+                print_cert(cert)?;
+            }
+        }
+
+        let keys = key
+            .iter()
+            .map(|key| ("bundle key".to_string(), key, false))
+            .chain(
+                check_key
+                    .iter()
+                    .map(|(path, key)| (format!("{path:?}"), key, true)),
+            );
+        for (label, key, is_check_key) in keys {
+            let matched = certs
+                .iter()
+                .position(|cert| cert.public_key().is_ok_and(|pkey| pkey.public_eq(key)));
+            check_key_matched |= is_check_key && matched.is_some();
+            match matched {
+                Some(cert_idx) => println!("Key match:    ✅ {label} matches [{cert_idx}]"),
+                None => println!("Key match:    ❌ {label} does not match any certificate"),
+            }
+        }
+    }
+
+    if let Some((path, _)) = &check_key {
+        ensure!(
+            check_key_matched,
+            "private key {path:?} does not match any certificate"
+        );
+    }
+    Ok(())
+}
+
+/// Create a connector with the given trust and protocol options
+fn build_connector(insecure: bool, tls: &TlsOpts) -> Result<SslConnector> {
+    Ok(connector_builder(insecure, tls)?.build())
+}
+
+/// Configure a connector, insecure connectors skip certificate verification and allow any protocol version
+fn connector_builder(insecure: bool, tls: &TlsOpts) -> Result<SslConnectorBuilder> {
     let mut ssl_connector = SslConnector::builder(SslMethod::tls())?;
-    if opts.insecure {
+    if tls.ca_file.is_some() || tls.ca_dir.is_some() {
+        ssl_connector.set_cert_store(trust_store(tls)?);
+    }
+    if let Some(cert) = &tls.cert {
+        let key = tls.key.as_ref().unwrap_or(cert);
+        ssl_connector
+            .set_certificate_chain_file(cert)
+            .map_err(|e| anyhow!("failed to load client certificate {cert:?}: {e}"))?;
+        ssl_connector
+            .set_private_key_file(key, SslFiletype::PEM)
+            .map_err(|e| anyhow!("failed to load client key {key:?}: {e}"))?;
+        ssl_connector
+            .check_private_key()
+            .map_err(|_| anyhow!("client key {key:?} does not match the certificate {cert:?}"))?;
+    }
+    if insecure {
         ssl_connector.set_verify(openssl::ssl::SslVerifyMode::NONE);
         ssl_connector
             .set_min_proto_version(None)
-            .map_err(|e| anyhow!("{}", e.to_string()))?;
-    }
-    let ssl_connector = ssl_connector.build();
-
-    for (idx, input_host) in opts.hosts.iter().enumerate() {
-        let mut host = input_host.to_owned();
-        if !host.contains("://") {
-            host = format!("https://{}", host);
-        }
-        let url = Url::from_str(&host)?;
-        if url.scheme() != "https" {
-            return Err(anyhow!(
-                "only supported scheme is 'https://', received '{}'",
-                url.scheme()
-            ));
-        }
-
-        let host = url.host_str().ok_or(anyhow!("failed to get host"))?;
-        let port = url
-            .port_or_known_default()
-            .ok_or(anyhow!("failed to get port"))?;
-        let addr = format!("{}:{}", host, port);
-
-        let stream = TcpStream::connect(&addr)
-            .map_err(|e| anyhow!("failed to establish tcp connection to '{addr}': {e}"))?;
-        let stream = ssl_connector
-            .connect(host, stream)
-            .map_err(|e| anyhow!("ssl error connecting to '{host}': {e}", e = e.to_string()))?;
-
-        let chain = stream
-            .ssl()
-            .peer_cert_chain()
-            .ok_or(anyhow!("failed to get peer certificate chain"))?;
-        let cert = chain
-            .get(0)
-            .ok_or(anyhow!("failed to get peer certificate"))?;
-
-        println!("Host:     {}", host);
-        println!("Version:      {}", cert.version());
+            .map_err(|e| anyhow!("{e}"))?;
+    }
+    Ok(ssl_connector)
+}
+
+/// Establish a TLS connection to a host given as `host[:port]` or `scheme://host[:port]`,
+/// returns the host name along with the stream.
+/// Schemes other than `https` are upgraded with their protocol's STARTTLS exchange first.
+fn connect(
+    ssl_connector: &SslConnector,
+    input_host: &str,
+    connect_opts: &ConnectOpts,
+) -> Result<(String, SslStream<TcpStream>)> {
+    let mut host = input_host.to_owned();
+    if !host.contains("://") {
+        host = format!("https://{}", host);
+    }
+    let url = Url::from_str(&host)?;
+    let scheme = url.scheme().to_lowercase();
+    if scheme != "https" && !starttls::is_supported(&scheme) {
+        return Err(anyhow!(
+            "unsupported scheme '{scheme}', expected one of 'https', {}",
+            starttls::SCHEMES
+                .iter()
+                .map(|s| format!("'{s}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let host = url.host_str().ok_or(anyhow!("failed to get host"))?;
+    let port = match url.port() {
+        Some(port) => port,
+        None => match utils::port_from_protocol(&scheme) {
+            0 => bail!("failed to get port"),
+            port => port,
+        },
+    };
+    let addr = match &connect_opts.connect {
+        Some(connect) => connect_addr(connect, port),
+        None => format!("{}:{}", host, port),
+    };
+
+    let timeout = Duration::from_millis(connect_opts.timeout);
+    let mut stream = connect_tcp(&addr, timeout)
+        .map_err(|e| anyhow!("failed to establish tcp connection to '{addr}': {e}"))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    if scheme != "https" {
+        starttls::upgrade(&scheme, &mut stream, host)
+            .map_err(|e| anyhow!("STARTTLS failed for '{addr}': {e}"))?;
+    }
+    // the certificate is verified against the name sent as SNI
+    let server_name = connect_opts.sni.as_deref().unwrap_or(host);
+    let mut config = ssl_connector.configure()?;
+    config.set_use_server_name_indication(!connect_opts.no_sni);
+    config.set_status_type(StatusType::OCSP)?;
+    if let Some(alpn) = &connect_opts.alpn {
+        config.set_alpn_protos(&alpn_wire_format(alpn)?)?;
+    }
+
+    // keep the first verification failure, the handshake error alone doesn't say where it happened
+    let failure = Arc::new(Mutex::new(None));
+    let verify_mode = config.verify_mode();
+    let callback_failure = failure.clone();
+    config.set_verify_callback(verify_mode, move |preverify_ok, ctx| {
+        if !preverify_ok {
+            let mut failure = callback_failure.lock().unwrap();
+            if failure.is_none() {
+                *failure = Some((
+                    ctx.error(),
+                    ctx.error_depth(),
+                    ctx.current_cert()
+                        .map(|cert| display_name_inline(cert.subject_name())),
+                ));
+            }
+        }
+        preverify_ok
+    });
+
+    let stream =
+        config
+            .connect(server_name, stream)
+            .map_err(|e| match failure.lock().unwrap().take() {
+                Some((error, depth, subject)) => anyhow!(
+                    "certificate verification failed for '{server_name}' at depth {depth}{}: {}",
+                    subject.map(|s| format!(" ({s})")).unwrap_or_default(),
+                    error.error_string()
+                ),
+                None => anyhow!("ssl error connecting to '{host}': {e}"),
+            })?;
+    stream.get_ref().set_read_timeout(None)?;
+    stream.get_ref().set_write_timeout(None)?;
+    Ok((host.to_string(), stream))
+}
+
+/// Connect to the first address the host resolves to that accepts within the timeout
+fn connect_tcp(addr: &str, timeout: Duration) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(match last_error {
+        Some(e) => e.into(),
+        None => anyhow!("no addresses found"),
+    })
+}
+
+/// Encode comma separated ALPN protocols as length prefixed strings
+fn alpn_wire_format(alpn: &str) -> Result<Vec<u8>> {
+    let mut wire = Vec::new();
+    for protocol in alpn.split(',').map(str::trim) {
+        ensure!(
+            (1..=255).contains(&protocol.len()),
+            "invalid ALPN protocol '{protocol}': must be 1 to 255 bytes long"
+        );
+        wire.push(protocol.len() as u8);
+        wire.extend(protocol.as_bytes());
+    }
+    Ok(wire)
+}
+
+/// Display what was negotiated during the handshake
+fn print_session(ssl: &SslRef, chain: &StackRef<X509>, store: &X509Store) -> Result<()> {
+    println!("Protocol: {}", ssl.version_str());
+    if let Some(cipher) = ssl.current_cipher() {
         println!(
-            "Serial:       {}",
-            cert.serial_number()
-                .to_bn()
-                .map_err(|e| anyhow!("failed to parse cert serial number: {e}"))?
-                .to_hex_str()
-                .map_err(|e| anyhow!("failed to parse serial number to hex: {e}"))
-                .and_then(display_hex)?
+            "Cipher:   {} ({} bits)",
+            cipher.standard_name().unwrap_or(cipher.name()),
+            cipher.bits().secret
         );
-        println!("Not before:   {}", cert.not_before());
-        println!("Not after:    {}", cert.not_after());
-        println!("Subject:      {}", display_nameref(cert.subject_name())?);
-        println!("Issuer:       {}", display_nameref(cert.issuer_name())?);
+    }
+    println!("Group:    {}", display_key_exchange(ssl));
+    println!(
+        "ALPN:     {}",
+        ssl.selected_alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).to_string())
+            .unwrap_or("<none>".to_string())
+    );
+    println!("Staple:   {}", display_ocsp_staple(ssl, chain, store)?);
+    Ok(())
+}
 
-        if idx < opts.hosts.len() - 1 {
-            println!();
+/// The key exchange group, taken from the server's ephemeral key
+fn display_key_exchange(ssl: &SslRef) -> String {
+    let Ok(key) = ssl.peer_tmp_key() else {
+        return "<none>".to_string();
+    };
+    match key.id() {
+        Id::X25519 => "X25519".to_string(),
+        Id::X448 => "X448".to_string(),
+        Id::EC => key
+            .ec_key()
+            .ok()
+            .and_then(|ec| ec.group().curve_name())
+            .and_then(|nid| nid.short_name().ok())
+            .unwrap_or("EC")
+            .to_string(),
+        Id::DH => format!("DH ({} bits)", key.bits()),
+        _ => format!("<unknown> ({} bits)", key.bits()),
+    }
+}
+
+/// The certificate status from the stapled OCSP response, if one was sent
+fn display_ocsp_staple(ssl: &SslRef, chain: &StackRef<X509>, store: &X509Store) -> Result<String> {
+    let Some(der) = ssl.ocsp_status() else {
+        return Ok("<none>".to_string());
+    };
+    let response = OcspResponse::from_der(der)
+        .map_err(|e| anyhow!("failed to parse stapled OCSP response: {e}"))?;
+    let status = response.status();
+    if status != OcspResponseStatus::SUCCESSFUL {
+        return Ok(format!("❌ response status {}", status.as_raw()));
+    }
+    let basic = response.basic()?;
+    let (Some(leaf), Some(issuer)) = (chain.get(0), chain.get(1)) else {
+        return Ok("❔ issuer not sent, can't match the response to the certificate".to_string());
+    };
+    let cert_id = OcspCertId::from_cert(MessageDigest::sha1(), leaf, issuer)?;
+    let Some(cert_status) = basic.find_status(&cert_id) else {
+        return Ok("❌ response doesn't cover the certificate".to_string());
+    };
+
+    let mut display = match cert_status.status {
+        OcspCertStatus::GOOD => "✅ good".to_string(),
+        OcspCertStatus::REVOKED => format!(
+            "❌ revoked{}",
+            cert_status
+                .revocation_time
+                .map(|t| format!(" at {t}"))
+                .unwrap_or_default()
+        ),
+        _ => "❔ unknown".to_string(),
+    };
+    display.push_str(&format!(
+        ", this update {}, next update {}",
+        cert_status.this_update,
+        cert_status
+            .next_update()
+            .map(|t| t.to_string())
+            .unwrap_or("<none>".to_string())
+    ));
+    if cert_status.check_validity(300, None).is_err() {
+        display.push_str(", ❌ response is outside its validity period");
+    }
+    if basic.verify(chain, store, OcspFlag::empty()).is_err() {
+        display.push_str(", ❌ response signature does not verify");
+    }
+    Ok(display)
+}
+
+/// Build the address to connect to from an `ip[:port]` or `host[:port]` override
+fn connect_addr(connect: &str, default_port: u16) -> String {
+    if let Ok(addr) = connect.parse::<SocketAddr>() {
+        return addr.to_string();
+    }
+    if let Ok(ip) = connect.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return SocketAddr::new(ip, default_port).to_string();
+    }
+    match connect.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => connect.to_string(),
+        _ => format!("{connect}:{default_port}"),
+    }
+}
+
+/// Display the details of a certificate
+fn print_cert(cert: &X509Ref) -> Result<()> {
+    println!("Version:      {}", cert.version());
+    println!("Serial:       {}", display_serial(cert)?);
+    println!("Not before:   {}", cert.not_before());
+    println!("Not after:    {}", cert.not_after());
+    println!("Subject:      {}", display_nameref(cert.subject_name())?);
+    println!("Issuer:       {}", display_nameref(cert.issuer_name())?);
+    println!("SANs:         {}", display_list(&subject_alt_names(cert)));
+    println!(
+        "Public key:   {}",
+        display_public_key(cert.public_key()?.as_ref())?
+    );
+    println!(
+        "Signature:    {}",
+        cert.signature_algorithm().object().nid().long_name()?
+    );
+    println!(
+        "SHA-1:        {}",
+        display_hex_bytes(&cert.digest(MessageDigest::sha1())?)
+    );
+    println!(
+        "SHA-256:      {}",
+        display_hex_bytes(&cert.digest(MessageDigest::sha256())?)
+    );
+
+    let text = String::from_utf8_lossy(&cert.to_text()?).to_string();
+    for (label, extension) in [
+        ("Key usage:    ", "X509v3 Key Usage"),
+        ("Extended use: ", "X509v3 Extended Key Usage"),
+        ("Constraints:  ", "X509v3 Basic Constraints"),
+    ] {
+        println!(
+            "{label}{}",
+            match text_extension(&text, extension) {
+                Some((true, value)) => format!("{value} (critical)"),
+                Some((false, value)) => value,
+                None => "<none>".to_string(),
+            }
+        );
+    }
+
+    let mut ca_issuers = Vec::new();
+    let mut ocsp = Vec::new();
+    for access in cert.authority_info().into_iter().flatten() {
+        let Some(uri) = access.location().uri() else {
+            continue;
+        };
+        match access.method().nid() {
+            Nid::AD_CA_ISSUERS => ca_issuers.push(uri.to_string()),
+            Nid::AD_OCSP => ocsp.push(uri.to_string()),
+            _ => {}
         }
     }
+    let mut crls = Vec::new();
+    for point in cert.crl_distribution_points().into_iter().flatten() {
+        let Some(names) = point.distpoint().and_then(|p| p.fullname()) else {
+            continue;
+        };
+        crls.extend(
+            names
+                .iter()
+                .filter_map(|n| n.uri().map(|uri| uri.to_string())),
+        );
+    }
+    println!("CA issuers:   {}", display_list(&ca_issuers));
+    println!("OCSP:         {}", display_list(&ocsp));
+    println!("CRLs:         {}", display_list(&crls));
+    println!(
+        "SCTs:         {}",
+        display_list(&signed_certificate_timestamps(&text))
+    );
     Ok(())
 }
 
+/// Embedded Certificate Transparency timestamps from a certificate's text form,
+/// with the log ID in base64 as published in CT log lists
+fn signed_certificate_timestamps(text: &str) -> Vec<String> {
+    let mut lines = text.lines();
+    if !lines.any(|l| l.trim_start().starts_with("CT Precertificate SCTs:")) {
+        return Vec::new();
+    }
+
+    let mut scts: Vec<(String, String)> = Vec::new();
+    let mut field = "";
+    for line in lines {
+        let line = line.trim();
+        if line == "Signed Certificate Timestamp:" {
+            scts.push(Default::default());
+            continue;
+        }
+        let Some(sct) = scts.last_mut() else {
+            break;
+        };
+        let value = match line.split_once(':') {
+            Some((key, value))
+                if ["Version", "Log ID", "Timestamp", "Extensions", "Signature"]
+                    .contains(&key.trim()) =>
+            {
+                field = key.trim();
+                value.trim()
+            }
+            // the log ID and signature continue over several lines
+            _ if matches!(field, "Log ID" | "Signature") && line.contains(':') => line,
+            _ => break,
+        };
+        match field {
+            "Log ID" => sct.0.push_str(value),
+            "Timestamp" => sct.1.push_str(value),
+            _ => {}
+        }
+    }
+
+    scts.into_iter()
+        .map(|(log_id, timestamp)| {
+            let log_id = log_id
+                .split(':')
+                .filter_map(|b| u8::from_str_radix(b, 16).ok())
+                .collect::<Vec<_>>();
+            format!("{timestamp}, log {}", base64::encode_block(&log_id))
+        })
+        .collect()
+}
+
+fn display_serial(cert: &X509Ref) -> Result<String> {
+    cert.serial_number()
+        .to_bn()
+        .map_err(|e| anyhow!("failed to parse cert serial number: {e}"))?
+        .to_hex_str()
+        .map_err(|e| anyhow!("failed to parse serial number to hex: {e}"))
+        .and_then(display_hex)
+}
+
+/// Subject alternative names, prefixed with their type
+fn subject_alt_names(cert: &X509Ref) -> Vec<String> {
+    cert.subject_alt_names()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| {
+            if let Some(dns) = name.dnsname() {
+                Some(format!("DNS: {dns}"))
+            } else if let Some(ip) = name.ipaddress() {
+                match ip.len() {
+                    4 => <[u8; 4]>::try_from(ip).ok().map(IpAddr::from),
+                    16 => <[u8; 16]>::try_from(ip).ok().map(IpAddr::from),
+                    _ => None,
+                }
+                .map(|ip| format!("IP: {ip}"))
+            } else if let Some(email) = name.email() {
+                Some(format!("Email: {email}"))
+            } else {
+                name.uri().map(|uri| format!("URI: {uri}"))
+            }
+        })
+        .collect()
+}
+
+/// Display the algorithm and size or curve of a public key
+fn display_public_key(key: &PKeyRef<Public>) -> Result<String> {
+    Ok(match key.id() {
+        Id::RSA => format!("RSA {} bits", key.bits()),
+        Id::RSA_PSS => format!("RSA-PSS {} bits", key.bits()),
+        Id::DSA => format!("DSA {} bits", key.bits()),
+        Id::EC => format!(
+            "ECDSA {} ({} bits)",
+            key.ec_key()?
+                .group()
+                .curve_name()
+                .and_then(|nid| nid.short_name().ok())
+                .unwrap_or("unknown curve"),
+            key.bits()
+        ),
+        Id::ED25519 => "Ed25519".to_string(),
+        Id::ED448 => "Ed448".to_string(),
+        _ => format!("unknown ({} bits)", key.bits()),
+    })
+}
+
+/// Find an extension in a certificate's text form, returns whether it is critical and its value
+fn text_extension(text: &str, extension: &str) -> Option<(bool, String)> {
+    let mut lines = text.lines();
+    let header = lines.find(|l| l.trim_start().starts_with(&format!("{extension}:")))?;
+    let value = lines.next()?.trim().to_string();
+    Some((header.trim_end().ends_with("critical"), value))
+}
+
+/// Display a list of values on their own lines
+fn display_list(values: &[String]) -> String {
+    match values.is_empty() {
+        true => "<none>".to_string(),
+        false => values.iter().fold(String::new(), |mut acc, v| {
+            acc.push_str(&format!("\n- {v}"));
+            acc
+        }),
+    }
+}
+
+/// Display every certificate of a chain, whether each one is issued by the next,
+/// and the trust anchor from the local store that completes it
+fn print_chain(chain: &StackRef<X509>, store: &X509Store) -> Result<()> {
+    let certs = chain.iter().collect::<Vec<_>>();
+    for (idx, cert) in certs.iter().enumerate() {
+        println!();
+        println!("[{idx}]");
+        print_cert(cert)?;
+        let link = match certs.get(idx + 1) {
+            Some(next) => match next.issued(cert) {
+                X509VerifyResult::OK => match cert.verify(next.public_key()?.as_ref())? {
+                    true => format!("✅ Issued and signed by [{}]", idx + 1),
+                    false => format!("❌ Signature does not verify against [{}]", idx + 1),
+                },
+                e => format!("❌ Not issued by [{}] ({})", idx + 1, e.error_string()),
+            },
+            None => match cert.issued(cert) {
+                X509VerifyResult::OK => "Self-signed, included in the chain".to_string(),
+                _ => format!(
+                    "Issuer not included in the chain: {}",
+                    display_name_inline(cert.issuer_name())
+                ),
+            },
+        };
+        println!("Link:         {link}");
+    }
+
+    println!();
+    match verify_chain(store, chain)? {
+        Ok(anchor) => println!(
+            "Trust anchor: ✅ {} (trust store)",
+            display_name_inline(anchor.subject_name())
+        ),
+        Err((e, depth)) => println!(
+            "Trust anchor: ❌ chain does not verify at depth {depth}: {}",
+            e.error_string()
+        ),
+    }
+    Ok(())
+}
+
+/// The trusted certificates used to verify chains, the system trust store unless CA files are given
+fn trust_store(tls: &TlsOpts) -> Result<X509Store> {
+    let mut store = X509StoreBuilder::new()?;
+    if tls.ca_file.is_none() && tls.ca_dir.is_none() {
+        store
+            .set_default_paths()
+            .map_err(|e| anyhow!("failed to load the local trust store: {e}"))?;
+        return Ok(store.build());
+    }
+
+    let mut ca_files = Vec::from_iter(tls.ca_file.clone());
+    if let Some(ca_dir) = &tls.ca_dir {
+        let entries = std::fs::read_dir(ca_dir)
+            .map_err(|e| anyhow!("Unable to read directory '{:?}': {}", ca_dir, e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() {
+                ca_files.push(path);
+            }
+        }
+    }
+    let mut loaded = 0;
+    for path in ca_files.iter() {
+        let bytes =
+            std::fs::read(path).map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))?;
+        match X509::stack_from_pem(&bytes) {
+            Ok(certs) => {
+                for cert in certs {
+                    store.add_cert(cert)?;
+                    loaded += 1;
+                }
+            }
+            // directories may hold other files alongside the certificates
+            Err(e) if tls.ca_file.as_ref() != Some(path) => {
+                debug!(target: "ssl", "skipping {path:?}: {e}")
+            }
+            Err(e) => bail!("failed to parse CA certificates in {path:?}: {e}"),
+        }
+    }
+    ensure!(loaded > 0, "no CA certificates found in {ca_files:?}");
+    Ok(store.build())
+}
+
+/// Verify a chain as sent by a server against the trust store.
+/// Returns the trust anchor that completed the chain, or the verification error and the depth it occurred at.
+fn verify_chain(
+    store: &X509Store,
+    chain: &StackRef<X509>,
+) -> Result<std::result::Result<X509, (X509VerifyResult, u32)>> {
+    let leaf = chain
+        .get(0)
+        .ok_or(anyhow!("failed to get peer certificate"))?;
+    let mut untrusted = Stack::new()?;
+    for cert in chain.iter().skip(1) {
+        untrusted.push(cert.to_owned())?;
+    }
+    let mut ctx = X509StoreContext::new()?;
+    Ok(ctx.init(store, leaf, &untrusted, |ctx| {
+        if !ctx.verify_cert()? {
+            return Ok(Err((ctx.error(), ctx.error_depth())));
+        }
+        Ok(ctx
+            .chain()
+            .and_then(|chain| chain.iter().last())
+            .map(|anchor| anchor.to_owned())
+            .ok_or((ctx.error(), ctx.error_depth())))
+    })?)
+}
+
+/// Display a name on a single line, e.g. `CN=example.com, O=Example`
+fn display_name_inline(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            format!(
+                "{}={}",
+                entry.object().nid().short_name().unwrap_or("?"),
+                entry.data().to_string().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Display a nameref object's entries
 fn display_nameref(nameref: &openssl::x509::X509NameRef) -> Result<String> {
     let mut output = String::new();
@@ -91,7 +964,7 @@ fn display_nameref(nameref: &openssl::x509::X509NameRef) -> Result<String> {
         output.push_str(&format!(
             "- {:?}: {:?}",
             entry.object(),
-            entry.data().as_utf8()?
+            entry.data().to_string()?
         ));
         if idx != nameref.entries().count() - 1 {
             output.push('\n');
@@ -102,7 +975,7 @@ fn display_nameref(nameref: &openssl::x509::X509NameRef) -> Result<String> {
 
 /// Display a hex string with colons
 fn display_hex(s: OpensslString) -> anyhow::Result<String> {
-    if s.len() % 2 != 0 {
+    if !s.len().is_multiple_of(2) {
         bail!("invalid hex string '{s}': Hex is not even");
     }
     Ok(s.chars()
@@ -120,3 +993,12 @@ fn display_hex(s: OpensslString) -> anyhow::Result<String> {
         .rev()
         .collect::<String>())
 }
+
+/// Display bytes as an uppercase hex string with colons
+fn display_hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,216 @@
+use crate::*;
+use futures::StreamExt;
+use openssl::{
+    asn1::{Asn1Time, Asn1TimeRef},
+    ssl::SslStream,
+};
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// hosts to check
+    #[clap(required_unless_present = "hosts_file")]
+    hosts: Vec<String>,
+    #[clap(flatten)]
+    batch: super::BatchOpts,
+    /// remaining validity below which a certificate is a warning (e.g. 30d, 12h)
+    // thresholds are parsed by the check, so an invalid one exits as UNKNOWN rather than with clap's code
+    #[clap(short, long, default_value = "30d")]
+    warn: String,
+    /// remaining validity below which a certificate is critical (e.g. 7d, 12h)
+    #[clap(short, long, default_value = "7d")]
+    crit: String,
+    #[clap(flatten)]
+    connect: super::ConnectOpts,
+}
+
+/// Checks every certificate each host sends, exiting with the worst status:
+/// 0 (OK), 1 (WARNING), 2 (CRITICAL) or 3 (UNKNOWN, e.g. the host couldn't be reached)
+pub async fn main(opts: &Opts) -> Result<()> {
+    // a check that can't run is unknown, the usual exit code 1 would read as a warning
+    let worst = check(opts).await.unwrap_or_else(|e| {
+        println!("{: <9} {e}", Status::Unknown);
+        Status::Unknown
+    });
+    exit(worst.exit_code())
+}
+
+/// Check each host and print its status, returning the worst one
+async fn check(opts: &Opts) -> Result<Status> {
+    let thresholds = Thresholds {
+        warn: opts.warn.parse()?,
+        crit: opts.crit.parse()?,
+    };
+    ensure!(
+        thresholds.crit <= thresholds.warn,
+        "critical threshold must not be greater than the warning threshold"
+    );
+
+    // expired or untrusted certificates must still be read to report on them
+    let ssl_connector = super::build_connector(true, &super::TlsOpts::default())?;
+    let now = Asn1Time::days_from_now(0)?;
+
+    let hosts = opts.batch.hosts(&opts.hosts)?;
+    let mut results =
+        super::connect_all(&ssl_connector, hosts, &opts.connect, opts.batch.concurrency);
+
+    let mut worst = Status::Ok;
+    while let Some((input_host, result)) = results.next().await {
+        let certs = match result.and_then(|(_, stream)| cert_expiries(&stream, &now)) {
+            Ok(certs) => certs,
+            Err(e) => {
+                println!("{: <9} {input_host}\t{e}", Status::Unknown);
+                worst = worst.max(Status::Unknown);
+                continue;
+            }
+        };
+
+        let status = certs
+            .iter()
+            .map(|cert| thresholds.status(cert.remaining))
+            .max()
+            .unwrap_or(Status::Unknown);
+        worst = worst.max(status);
+        let remaining = certs.iter().map(|cert| cert.remaining).min();
+        println!(
+            "{status: <9} {input_host}\t{}",
+            match remaining {
+                Some(remaining) => display_remaining(remaining),
+                None => "no certificates received".to_string(),
+            }
+        );
+        for (idx, cert) in certs.iter().enumerate() {
+            println!(
+                "\t[{idx}] {: <9} {}\t{} ({})",
+                thresholds.status(cert.remaining),
+                display_remaining(cert.remaining),
+                cert.subject,
+                cert.not_after
+            );
+        }
+    }
+    Ok(worst)
+}
+
+/// Remaining validity below which a certificate is a warning or critical
+struct Thresholds {
+    warn: Threshold,
+    crit: Threshold,
+}
+
+impl Thresholds {
+    fn status(&self, remaining: i64) -> Status {
+        if remaining < self.crit.0 {
+            Status::Critical
+        } else if remaining < self.warn.0 {
+            Status::Warning
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+/// Expiry of a single certificate in a host's chain
+struct CertExpiry {
+    subject: String,
+    not_after: String,
+    /// Seconds until the certificate expires, negative once it has
+    remaining: i64,
+}
+
+fn cert_expiries(stream: &SslStream<TcpStream>, now: &Asn1TimeRef) -> Result<Vec<CertExpiry>> {
+    let chain = stream
+        .ssl()
+        .peer_cert_chain()
+        .ok_or(anyhow!("failed to get peer certificate chain"))?;
+    let mut certs = Vec::with_capacity(chain.len());
+    for cert in chain.iter() {
+        let diff = now.diff(cert.not_after())?;
+        certs.push(CertExpiry {
+            subject: super::display_name_inline(cert.subject_name()),
+            not_after: cert.not_after().to_string(),
+            remaining: diff.days as i64 * 86400 + diff.secs as i64,
+        });
+    }
+    Ok(certs)
+}
+
+/// Display remaining validity in whole days, or hours when less than a day is left
+fn display_remaining(remaining: i64) -> String {
+    match remaining {
+        r if r < 0 => format!("expired {} day(s) ago", -r / 86400),
+        r if r < 86400 => format!("{} hour(s) remaining", r / 3600),
+        r => format!("{} day(s) remaining", r / 86400),
+    }
+}
+
+/// Monitoring plugin status, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl Status {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Unknown => "UNKNOWN",
+            Status::Critical => "CRITICAL",
+        }
+        .fmt(f)
+    }
+}
+
+/// A length of time in seconds, given as a number with a `d` (days), `h` (hours) or `w` (weeks) suffix,
+/// numbers without a suffix are days
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Threshold(i64);
+
+impl std::str::FromStr for Threshold {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(idx) => s.split_at(idx),
+            None => (s.as_str(), "d"),
+        };
+        let value = value
+            .parse::<i64>()
+            .map_err(|e| anyhow!("invalid threshold '{s}': {e}"))?;
+        let unit = match unit {
+            "h" => 3600,
+            "d" => 86400,
+            "w" => 7 * 86400,
+            _ => bail!("invalid threshold '{s}': unit must be one of 'h', 'd' or 'w'"),
+        };
+        value
+            .checked_mul(unit)
+            .map(Self)
+            .ok_or(anyhow!("invalid threshold '{s}': too large"))
+    }
+}
+
+#[test]
+fn test_threshold_from_str() -> Result<()> {
+    assert_eq!("30d".parse::<Threshold>()?, Threshold(30 * 86400));
+    assert_eq!("12h".parse::<Threshold>()?, Threshold(12 * 3600));
+    assert_eq!("2w".parse::<Threshold>()?, Threshold(14 * 86400));
+    assert_eq!("7".parse::<Threshold>()?, Threshold(7 * 86400));
+    assert!("7m".parse::<Threshold>().is_err());
+    assert!("9223372036854775807w".parse::<Threshold>().is_err());
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! Compare certificates between hosts, or against a snapshot taken earlier
+use crate::*;
+use futures::StreamExt;
+use openssl::{asn1::Asn1Time, hash::MessageDigest, x509::X509Ref};
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// hosts to compare, represented in output as < (blue) and > (red),
+    /// or a single host to compare against a snapshot or save one of
+    #[clap(required = true, num_args = 1..=2)]
+    hosts: Vec<String>,
+    /// snapshot (JSON) to compare the host against, as written by `--save`
+    #[clap(long, value_name = "PATH")]
+    snapshot: Option<PathBuf>,
+    /// save a snapshot (JSON) of the last host's certificates
+    #[clap(long, value_name = "PATH")]
+    save: Option<PathBuf>,
+    #[clap(flatten)]
+    connect: super::ConnectOpts,
+}
+
+/// Exits with 1 when the certificates differ, so the comparison can be scripted
+pub async fn main(opts: &Opts) -> Result<()> {
+    // certificates are compared, not verified
+    let ssl_connector = super::build_connector(true, &super::TlsOpts::default())?;
+
+    let mut snapshots = Vec::with_capacity(2);
+    if let Some(path) = &opts.snapshot {
+        ensure!(
+            opts.hosts.len() == 1,
+            "only one host can be compared against a snapshot"
+        );
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))?;
+        snapshots.push(
+            serde_json::from_str::<Snapshot>(&json)
+                .map_err(|e| anyhow!("invalid snapshot {path:?}: {e}"))?,
+        );
+    }
+    let mut results = super::connect_all(
+        &ssl_connector,
+        opts.hosts.clone(),
+        &opts.connect,
+        opts.hosts.len(),
+    );
+    while let Some((input_host, result)) = results.next().await {
+        let (host, stream) =
+            result.map_err(|e| anyhow!("failed to connect to '{input_host}': {e}"))?;
+        let chain = stream
+            .ssl()
+            .peer_cert_chain()
+            .ok_or(anyhow!("failed to get peer certificate chain"))?;
+        snapshots.push(Snapshot {
+            host,
+            taken: Asn1Time::days_from_now(0)?.to_string(),
+            chain: chain
+                .iter()
+                .map(CertSnapshot::new)
+                .collect::<Result<Vec<_>>>()?,
+        });
+    }
+
+    if let Some(path) = &opts.save {
+        let snapshot = snapshots.last().ok_or(anyhow!("no host to save"))?;
+        std::fs::write(path, serde_json::to_string_pretty(snapshot)?)
+            .map_err(|e| anyhow!("failed to write {path:?}: {e}"))?;
+        println!("Saved snapshot of {} to {path:?}", snapshot.host);
+    }
+
+    match snapshots.as_slice() {
+        [old, new] => {
+            if opts.save.is_some() {
+                println!();
+            }
+            if print_diff(old, new)? {
+                exit(1);
+            }
+            Ok(())
+        }
+        _ if opts.save.is_some() => Ok(()),
+        _ => bail!("two hosts, or a host and a snapshot, are needed to compare"),
+    }
+}
+
+/// The certificates a host sent at a point in time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    host: String,
+    taken: String,
+    chain: Vec<CertSnapshot>,
+}
+
+/// The fields of a certificate that are compared
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CertSnapshot {
+    subject: String,
+    issuer: String,
+    serial: String,
+    sans: Vec<String>,
+    public_key: String,
+    /// SHA-256 of the DER SubjectPublicKeyInfo, tells apart rotated keys of the same type
+    public_key_sha256: String,
+    signature: String,
+    not_before: String,
+    not_after: String,
+    sha256: String,
+}
+
+impl CertSnapshot {
+    fn new(cert: &X509Ref) -> Result<Self> {
+        Ok(Self {
+            subject: super::display_name_inline(cert.subject_name()),
+            issuer: super::display_name_inline(cert.issuer_name()),
+            serial: super::display_serial(cert)?,
+            sans: super::subject_alt_names(cert),
+            public_key: super::display_public_key(cert.public_key()?.as_ref())?,
+            public_key_sha256: super::display_hex_bytes(&openssl::sha::sha256(
+                &cert.public_key()?.public_key_to_der()?,
+            )),
+            signature: cert
+                .signature_algorithm()
+                .object()
+                .nid()
+                .long_name()?
+                .to_string(),
+            not_before: cert.not_before().to_string(),
+            not_after: cert.not_after().to_string(),
+            sha256: super::display_hex_bytes(&cert.digest(MessageDigest::sha256())?),
+        })
+    }
+
+    /// One line summary used to compare chain certificates
+    fn summary(&self) -> String {
+        format!("{} (expires {})", self.subject, self.not_after)
+    }
+}
+
+/// Print the differences between two snapshots, returns whether there are any
+fn print_diff(old: &Snapshot, new: &Snapshot) -> Result<bool> {
+    println!("{}| {} ({})", "<".blue(), old.host, old.taken);
+    println!("{}| {} ({})", ">".red(), new.host, new.taken);
+
+    let (Some(old_leaf), Some(new_leaf)) = (old.chain.first(), new.chain.first()) else {
+        bail!("no certificates to compare");
+    };
+    let mut differences = Vec::new();
+    println!("\nLeaf:");
+    for (label, old_value, new_value) in [
+        ("Subject", &old_leaf.subject, &new_leaf.subject),
+        ("Issuer", &old_leaf.issuer, &new_leaf.issuer),
+        ("Serial", &old_leaf.serial, &new_leaf.serial),
+        ("Public key", &old_leaf.public_key, &new_leaf.public_key),
+        (
+            "Key SHA-256",
+            &old_leaf.public_key_sha256,
+            &new_leaf.public_key_sha256,
+        ),
+        ("Signature", &old_leaf.signature, &new_leaf.signature),
+        ("Not before", &old_leaf.not_before, &new_leaf.not_before),
+        ("Not after", &old_leaf.not_after, &new_leaf.not_after),
+        ("SHA-256", &old_leaf.sha256, &new_leaf.sha256),
+    ] {
+        if print_field(label, old_value, new_value) {
+            differences.push(label);
+        }
+    }
+
+    let mut sans_differ = false;
+    for (idx, san) in old_leaf
+        .sans
+        .iter()
+        .chain(
+            new_leaf
+                .sans
+                .iter()
+                .filter(|san| !old_leaf.sans.contains(san)),
+        )
+        .enumerate()
+    {
+        let label = match idx {
+            0 => "SANs:",
+            _ => "",
+        };
+        let marker = match (old_leaf.sans.contains(san), new_leaf.sans.contains(san)) {
+            (true, true) => "=".green(),
+            (true, false) => "<".blue(),
+            _ => ">".red(),
+        };
+        sans_differ |= !(old_leaf.sans.contains(san) && new_leaf.sans.contains(san));
+        println!("{label: <14}{marker}| {san}");
+    }
+    if old_leaf.sans.is_empty() && new_leaf.sans.is_empty() {
+        println!("{: <14}{}| <none>", "SANs:", "=".green());
+    }
+    if sans_differ {
+        differences.push("SANs");
+    }
+
+    println!("\nChain:");
+    let mut chain_differs = false;
+    for idx in 1..old.chain.len().max(new.chain.len()) {
+        let label = format!("[{idx}]");
+        match (old.chain.get(idx), new.chain.get(idx)) {
+            (Some(old_cert), Some(new_cert)) => {
+                // the fingerprint catches reissued intermediates with the same subject
+                chain_differs |= old_cert.sha256 != new_cert.sha256;
+                if old_cert.sha256 == new_cert.sha256 {
+                    println!("{label: <14}{}| {}", "=".green(), old_cert.summary());
+                } else {
+                    println!("{label: <14}{}| {}", "<".blue(), old_cert.summary());
+                    println!("{: <14}{}| {}", "", ">".red(), new_cert.summary());
+                }
+            }
+            (Some(old_cert), None) => {
+                chain_differs = true;
+                println!("{label: <14}{}| {}", "<".blue(), old_cert.summary());
+            }
+            (None, Some(new_cert)) => {
+                chain_differs = true;
+                println!("{label: <14}{}| {}", ">".red(), new_cert.summary());
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    if old.chain.len() <= 1 && new.chain.len() <= 1 {
+        println!("<none>");
+    }
+    if chain_differs {
+        differences.push("Chain");
+    }
+
+    println!(
+        "\nDifferences:  {}",
+        match differences.is_empty() {
+            true => "none".to_string(),
+            false => differences.join(", "),
+        }
+    );
+    Ok(!differences.is_empty())
+}
+
+/// Print a field with its difference marker, returns whether it differs
+fn print_field(label: &str, old: &str, new: &str) -> bool {
+    let label = format!("{label}:");
+    if old == new {
+        println!("{label: <14}{}| {old}", "=".green());
+        return false;
+    }
+    println!("{label: <14}{}| {old}", "<".blue());
+    println!("{: <14}{}| {new}", "", ">".red());
+    true
+}
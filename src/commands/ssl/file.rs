@@ -0,0 +1,87 @@
+//! Certificates and private keys read from local files
+use crate::*;
+use openssl::{
+    pkcs12::Pkcs12,
+    pkey::{PKey, Private},
+    stack::Stack,
+    x509::X509,
+};
+use std::path::Path;
+
+/// Read the password from the named environment variable
+pub fn password(pass_env: Option<&str>) -> Result<Option<String>> {
+    pass_env
+        .map(|var| {
+            std::env::var(var).map_err(|e| anyhow!("failed to read password from '{var}': {e}"))
+        })
+        .transpose()
+}
+
+/// Read every certificate of a PEM file, or the single certificate of a DER file
+pub fn read_certs(path: &Path) -> Result<Stack<X509>> {
+    let bytes = read(path)?;
+    let mut certs = Stack::new()?;
+    if is_pem(&bytes) {
+        for cert in X509::stack_from_pem(&bytes)
+            .map_err(|e| anyhow!("failed to parse PEM certificates in {path:?}: {e}"))?
+        {
+            certs.push(cert)?;
+        }
+    } else {
+        certs.push(
+            X509::from_der(&bytes)
+                .map_err(|e| anyhow!("failed to parse DER certificate in {path:?}: {e}"))?,
+        )?;
+    }
+    ensure!(!certs.is_empty(), "no certificates found in {path:?}");
+    Ok(certs)
+}
+
+/// Read the certificates of a PKCS#12 bundle, leaf first, along with its private key
+pub fn read_p12(path: &Path, password: &str) -> Result<(Stack<X509>, Option<PKey<Private>>)> {
+    let parsed = Pkcs12::from_der(&read(path)?)
+        .and_then(|p12| p12.parse2(password))
+        .map_err(|e| anyhow!("failed to parse PKCS#12 bundle {path:?}: {e}"))?;
+    let mut certs = Stack::new()?;
+    if let Some(cert) = parsed.cert {
+        certs.push(cert)?;
+    }
+    for cert in parsed.ca.into_iter().flatten() {
+        certs.push(cert)?;
+    }
+    ensure!(!certs.is_empty(), "no certificates found in {path:?}");
+    Ok((certs, parsed.pkey))
+}
+
+/// Read a PEM or DER private key, PEM keys may be encrypted with the password
+pub fn read_key(path: &Path, password: Option<&str>) -> Result<PKey<Private>> {
+    let bytes = read(path)?;
+    match (is_pem(&bytes), password) {
+        (true, Some(password)) => {
+            PKey::private_key_from_pem_passphrase(&bytes, password.as_bytes())
+        }
+        (true, None) => {
+            // without a password OpenSSL would prompt for one on the terminal
+            let mut encrypted = false;
+            let key = PKey::private_key_from_pem_callback(&bytes, |_| {
+                encrypted = true;
+                Ok(0)
+            });
+            ensure!(
+                !encrypted,
+                "private key in {path:?} is encrypted, pass its password with --pass-env"
+            );
+            key
+        }
+        (false, _) => PKey::private_key_from_der(&bytes),
+    }
+    .map_err(|e| anyhow!("failed to parse private key in {path:?}: {e}"))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))
+}
+
+fn is_pem(bytes: &[u8]) -> bool {
+    bytes.windows(11).any(|w| w == b"-----BEGIN ")
+}
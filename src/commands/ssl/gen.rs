@@ -0,0 +1,390 @@
+//! Private CAs, certificates and CSRs for local testing
+use crate::*;
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, PKeyRef, Private},
+    rsa::Rsa,
+    stack::Stack,
+    x509::{
+        extension::{
+            AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage,
+            SubjectAlternativeName, SubjectKeyIdentifier,
+        },
+        X509Builder, X509Name, X509NameBuilder, X509Req, X509,
+    },
+};
+use std::path::Path;
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Command,
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Command {
+    /// Create a private certificate authority
+    Ca(CaOpts),
+    /// Issue a certificate with SANs, signed by a CA or self-signed
+    Cert(CertOpts),
+    /// Create a certificate signing request
+    Csr(CsrOpts),
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct CaOpts {
+    /// common name of the CA
+    #[clap(long, default_value = "Toolbelt Test CA")]
+    cn: String,
+    /// organization of the CA
+    #[clap(long)]
+    org: Option<String>,
+    /// days the CA is valid for
+    #[clap(long, default_value = "3650")]
+    days: u32,
+    #[clap(flatten)]
+    key: KeyOpts,
+    #[clap(flatten)]
+    out: OutOpts,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct CertOpts {
+    /// names (DNS names or IP addresses) the certificate is valid for
+    #[clap(long, required = true)]
+    san: Vec<String>,
+    /// common name, defaults to the first SAN
+    #[clap(long)]
+    cn: Option<String>,
+    /// organization of the subject
+    #[clap(long)]
+    org: Option<String>,
+    /// days the certificate is valid for
+    #[clap(long, default_value = "90")]
+    days: u32,
+    /// CA certificate (PEM) to sign with, the certificate is self-signed without one
+    #[clap(long, requires = "ca_key", value_name = "PATH")]
+    ca: Option<PathBuf>,
+    /// private key (PEM) of the CA
+    #[clap(long, requires = "ca", value_name = "PATH")]
+    ca_key: Option<PathBuf>,
+    /// environment variable holding the password of the CA private key
+    #[clap(long, requires = "ca_key", value_name = "VAR")]
+    pass_env: Option<String>,
+    #[clap(flatten)]
+    key: KeyOpts,
+    #[clap(flatten)]
+    out: OutOpts,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct CsrOpts {
+    /// names (DNS names or IP addresses) to request
+    #[clap(long)]
+    san: Vec<String>,
+    /// common name, defaults to the first SAN
+    #[clap(long, required_unless_present = "san")]
+    cn: Option<String>,
+    /// organization of the subject
+    #[clap(long)]
+    org: Option<String>,
+    #[clap(flatten)]
+    key: KeyOpts,
+    #[clap(flatten)]
+    out: OutOpts,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct KeyOpts {
+    /// key type: rsa, ecdsa (P-256) or ed25519
+    #[clap(long, default_value = "ecdsa")]
+    key_type: KeyType,
+    /// RSA key size in bits
+    #[clap(long, default_value = "2048")]
+    rsa_bits: u32,
+}
+
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct OutOpts {
+    /// file the certificate or CSR is written to, defaults to `<kind>.pem`
+    #[clap(short, long, value_name = "PATH")]
+    out: Option<PathBuf>,
+    /// file the private key is written to, defaults to the output with a `.key` extension
+    #[clap(long, value_name = "PATH")]
+    key_out: Option<PathBuf>,
+    /// overwrite existing files
+    #[clap(long)]
+    force: bool,
+}
+
+pub async fn main(opts: &Opts) -> Result<()> {
+    match &opts.cmd {
+        Command::Ca(opts) => gen_ca(opts),
+        Command::Cert(opts) => gen_cert(opts),
+        Command::Csr(opts) => gen_csr(opts),
+    }
+}
+
+fn gen_ca(opts: &CaOpts) -> Result<()> {
+    let key = opts.key.generate()?;
+    let name = build_name(&opts.cn, opts.org.as_deref())?;
+
+    let mut builder = cert_builder(&name, &name, &key, opts.days)?;
+    builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .key_cert_sign()
+            .crl_sign()
+            .build()?,
+    )?;
+    let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(ski)?;
+    builder.sign(&key, digest(&key))?;
+
+    opts.out
+        .write("ca", &builder.build().to_pem()?, &key, "certificate")
+}
+
+fn gen_cert(opts: &CertOpts) -> Result<()> {
+    let key = opts.key.generate()?;
+    let cn = opts.cn.as_deref().unwrap_or(&opts.san[0]);
+    let name = build_name(cn, opts.org.as_deref())?;
+
+    let ca = match (&opts.ca, &opts.ca_key) {
+        (Some(ca), Some(ca_key)) => {
+            let ca_cert = X509::from_pem(&read(ca)?)
+                .map_err(|e| anyhow!("failed to parse CA certificate {ca:?}: {e}"))?;
+            let text = String::from_utf8_lossy(&ca_cert.to_text()?).to_string();
+            ensure!(
+                super::text_extension(&text, "X509v3 Basic Constraints")
+                    .is_some_and(|(_, value)| value.contains("CA:TRUE")),
+                "{ca:?} is not a CA certificate (basicConstraints CA:TRUE)"
+            );
+            let password = super::file::password(opts.pass_env.as_deref())?;
+            let ca_key = super::file::read_key(ca_key, password.as_deref())?;
+            ensure!(
+                ca_cert.public_key()?.public_eq(&ca_key),
+                "CA private key does not match the CA certificate"
+            );
+            Some((ca_cert, ca_key))
+        }
+        _ => None,
+    };
+    let (issuer, signing_key) = match &ca {
+        Some((ca_cert, ca_key)) => (ca_cert.subject_name(), ca_key),
+        None => (name.as_ref(), &key),
+    };
+
+    let mut builder = cert_builder(&name, issuer, &key, opts.days)?;
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    let mut key_usage = KeyUsage::new();
+    key_usage.critical().digital_signature();
+    if key.id() == Id::RSA {
+        key_usage.key_encipherment();
+    }
+    builder.append_extension(key_usage.build()?)?;
+    builder.append_extension(
+        ExtendedKeyUsage::new()
+            .server_auth()
+            .client_auth()
+            .build()?,
+    )?;
+    let ctx = builder.x509v3_context(ca.as_ref().map(|(ca_cert, _)| ca_cert.as_ref()), None);
+    let san = subject_alt_names(&opts.san).build(&ctx)?;
+    let ski = SubjectKeyIdentifier::new().build(&ctx)?;
+    let aki = match ca.is_some() {
+        true => Some(AuthorityKeyIdentifier::new().keyid(true).build(&ctx)?),
+        false => None,
+    };
+    builder.append_extension(san)?;
+    builder.append_extension(ski)?;
+    if let Some(aki) = aki {
+        builder.append_extension(aki)?;
+    }
+    builder.sign(signing_key, digest(signing_key))?;
+
+    opts.out
+        .write("cert", &builder.build().to_pem()?, &key, "certificate")
+}
+
+fn gen_csr(opts: &CsrOpts) -> Result<()> {
+    let key = opts.key.generate()?;
+    let cn = match (&opts.cn, opts.san.first()) {
+        (Some(cn), _) | (None, Some(cn)) => cn,
+        (None, None) => bail!("a common name or SAN is required"),
+    };
+    let name = build_name(cn, opts.org.as_deref())?;
+
+    let mut builder = X509Req::builder()?;
+    builder.set_version(0)?;
+    builder.set_subject_name(&name)?;
+    builder.set_pubkey(&key)?;
+    if !opts.san.is_empty() {
+        let mut extensions = Stack::new()?;
+        extensions.push(subject_alt_names(&opts.san).build(&builder.x509v3_context(None))?)?;
+        builder.add_extensions(&extensions)?;
+    }
+    builder.sign(&key, digest(&key))?;
+
+    opts.out.write(
+        "csr",
+        &builder.build().to_pem()?,
+        &key,
+        "certificate signing request",
+    )
+}
+
+/// A throwaway self-signed ECDSA server certificate for the names, valid for a day
+pub fn self_signed(sans: &[String]) -> Result<(X509, PKey<Private>)> {
+    let key = PKey::from_ec_key(EcKey::generate(
+        EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?.as_ref(),
+    )?)?;
+    let name = build_name(&sans[0], None)?;
+    let mut builder = cert_builder(&name, &name, &key, 1)?;
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    builder
+        .append_extension(subject_alt_names(sans).build(&builder.x509v3_context(None, None))?)?;
+    builder.sign(&key, digest(&key))?;
+    Ok((builder.build(), key))
+}
+
+/// A certificate builder with the subject, issuer, key, a random serial and validity set
+fn cert_builder(
+    subject: &X509Name,
+    issuer: &openssl::x509::X509NameRef,
+    key: &PKeyRef<Private>,
+    days: u32,
+) -> Result<X509Builder> {
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(serial.to_asn1_integer()?.as_ref())?;
+    builder.set_subject_name(subject)?;
+    builder.set_issuer_name(issuer)?;
+    builder.set_pubkey(key)?;
+    builder.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
+    builder.set_not_after(Asn1Time::days_from_now(days)?.as_ref())?;
+    Ok(builder)
+}
+
+fn build_name(cn: &str, org: Option<&str>) -> Result<X509Name> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, cn)?;
+    if let Some(org) = org {
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, org)?;
+    }
+    Ok(name.build())
+}
+
+/// SAN extension with each name added as an IP address or DNS name
+fn subject_alt_names(sans: &[String]) -> SubjectAlternativeName {
+    let mut san = SubjectAlternativeName::new();
+    for name in sans {
+        match name.parse::<IpAddr>() {
+            Ok(_) => san.ip(name),
+            Err(_) => san.dns(name),
+        };
+    }
+    san
+}
+
+/// Ed25519 signs the message itself, so takes no digest
+fn digest(key: &PKeyRef<Private>) -> MessageDigest {
+    match key.id() {
+        Id::ED25519 => MessageDigest::null(),
+        _ => MessageDigest::sha256(),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))
+}
+
+impl KeyOpts {
+    fn generate(&self) -> Result<PKey<Private>> {
+        Ok(match self.key_type {
+            KeyType::Rsa => PKey::from_rsa(Rsa::generate(self.rsa_bits)?)?,
+            KeyType::Ecdsa => PKey::from_ec_key(EcKey::generate(
+                EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?.as_ref(),
+            )?)?,
+            KeyType::Ed25519 => PKey::generate_ed25519()?,
+        })
+    }
+}
+
+impl OutOpts {
+    /// Write the PEM output and its private key, refusing to overwrite files unless forced
+    fn write(
+        &self,
+        kind: &str,
+        pem: &[u8],
+        key: &PKeyRef<Private>,
+        description: &str,
+    ) -> Result<()> {
+        let out = self
+            .out
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("{kind}.pem")));
+        let key_out = self
+            .key_out
+            .clone()
+            .unwrap_or_else(|| out.with_extension("key"));
+        ensure!(
+            out != key_out,
+            "the output and key output must be different files"
+        );
+        for path in [&out, &key_out] {
+            ensure!(
+                self.force || !path.exists(),
+                "{path:?} already exists, use --force to overwrite it"
+            );
+        }
+
+        std::fs::write(&out, pem).map_err(|e| anyhow!("failed to write {out:?}: {e}"))?;
+        write_private(&key_out, &key.private_key_to_pem_pkcs8()?)
+            .map_err(|e| anyhow!("failed to write {key_out:?}: {e}"))?;
+        println!("Wrote {description} to {out:?}");
+        println!("Wrote private key to {key_out:?}");
+        Ok(())
+    }
+}
+
+/// Write a file only the owner can read, the key is never world-readable, not even briefly
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // the mode only applies to new files, an overwritten key keeps its permissions otherwise
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum KeyType {
+    Rsa,
+    Ecdsa,
+    Ed25519,
+}
+
+impl std::str::FromStr for KeyType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rsa" => Ok(Self::Rsa),
+            "ecdsa" | "ec" => Ok(Self::Ecdsa),
+            "ed25519" => Ok(Self::Ed25519),
+            _ => bail!("invalid key type '{s}': must be one of 'rsa', 'ecdsa' or 'ed25519'"),
+        }
+    }
+}
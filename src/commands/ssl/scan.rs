@@ -0,0 +1,302 @@
+//! Enumerate the protocol versions, cipher suites and groups a server accepts
+use crate::*;
+use openssl::ssl::{
+    SslCipherRef, SslConnector, SslConnectorBuilder, SslMethod, SslStream, SslVerifyMode,
+    SslVersion,
+};
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// host to scan, as `host[:port]` or `scheme://host[:port]`
+    #[clap(required = true)]
+    host: String,
+    #[clap(flatten)]
+    connect: super::ConnectOpts,
+}
+
+/// Protocol versions probed, newest first
+const VERSIONS: [(SslVersion, &str); 4] = [
+    (SslVersion::TLS1_3, "TLSv1.3"),
+    (SslVersion::TLS1_2, "TLSv1.2"),
+    (SslVersion::TLS1_1, "TLSv1.1"),
+    (SslVersion::TLS1, "TLSv1.0"),
+];
+
+/// Every cipher the local OpenSSL knows for TLS 1.2 and below, including those disabled by default
+const LEGACY_CIPHERS: &str = "ALL:COMPLEMENTOFALL";
+
+/// TLS 1.3 cipher suites, these can't be selected with a cipher string
+const TLS13_CIPHERS: [&str; 5] = [
+    "TLS_AES_256_GCM_SHA384",
+    "TLS_CHACHA20_POLY1305_SHA256",
+    "TLS_AES_128_GCM_SHA256",
+    "TLS_AES_128_CCM_SHA256",
+    "TLS_AES_128_CCM_8_SHA256",
+];
+
+/// Key exchange groups probed, the second field marks groups only usable with TLS 1.3
+const GROUPS: [(&str, bool); 11] = [
+    ("X25519MLKEM768", true),
+    ("X25519", false),
+    ("X448", false),
+    ("P-256", false),
+    ("P-384", false),
+    ("P-521", false),
+    ("ffdhe2048", true),
+    ("ffdhe3072", true),
+    ("ffdhe4096", true),
+    ("ffdhe6144", true),
+    ("ffdhe8192", true),
+];
+
+/// Probes the server with one handshake per option, so only what the local OpenSSL supports can be tested
+pub async fn main(opts: &Opts) -> Result<()> {
+    // a host that can't be reached at all shouldn't be reported as rejecting everything
+    let (host, _) =
+        super::connect_async(&connector(None)?.build(), &opts.host, &opts.connect).await?;
+    println!("Host:     {}", host);
+
+    let mut weak = 0;
+    let mut versions = Vec::new();
+    println!("\nProtocols:");
+    for (version, version_name) in VERSIONS {
+        let accepted = handshake(opts, connector(Some(version))?.build())
+            .await
+            .is_some();
+        let note = match (accepted, version_weakness(version)) {
+            (true, Some(reason)) => {
+                weak += 1;
+                format!("accepted\t(weak: {reason})")
+            }
+            (true, None) => "accepted".to_string(),
+            (false, _) => "rejected".to_string(),
+        };
+        println!("- {: <9} {note}", format!("{version_name}:"));
+        if accepted {
+            versions.push((version, version_name));
+        }
+    }
+
+    for (version, version_name) in versions.iter() {
+        let ciphers = accepted_ciphers(opts, *version).await?;
+        let order = match ciphers.len() {
+            0 | 1 => "",
+            _ => match server_enforces_order(opts, *version, &ciphers).await? {
+                true => " (server preference order)",
+                false => " (client preference order)",
+            },
+        };
+        println!("\n{version_name} ciphers{order}:");
+        if ciphers.is_empty() {
+            println!("<none>");
+        }
+        let width = ciphers.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for cipher in ciphers.iter() {
+            let reasons = cipher.weaknesses(*version);
+            print!(
+                "- {: <width$}  Kx={} Au={} Enc={} Mac={}",
+                cipher.name, cipher.kx, cipher.au, cipher.enc, cipher.mac
+            );
+            if reasons.is_empty() {
+                println!();
+            } else {
+                weak += 1;
+                println!("\t(weak: {})", reasons.join(", "));
+            }
+        }
+    }
+
+    if let Some((version, _)) = versions.first() {
+        let groups = accepted_groups(opts, *version).await?;
+        println!("\nGroups:   {}", super::display_list(&groups));
+    }
+
+    println!(
+        "\nWeak options: {}",
+        match weak {
+            0 => "none".to_string(),
+            n => n.to_string(),
+        }
+    );
+    Ok(())
+}
+
+/// Connector accepting anything the local OpenSSL can do, optionally pinned to a single version
+fn connector(version: Option<SslVersion>) -> Result<SslConnectorBuilder> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    builder.set_verify(SslVerifyMode::NONE);
+    builder.set_security_level(0);
+    builder.set_cipher_list(&format!("{LEGACY_CIPHERS}:@SECLEVEL=0"))?;
+    builder.set_min_proto_version(version)?;
+    builder.set_max_proto_version(version)?;
+    Ok(builder)
+}
+
+/// Attempt a handshake, a failure means the server rejected what was offered
+async fn handshake(opts: &Opts, connector: SslConnector) -> Option<SslStream<TcpStream>> {
+    match super::connect_async(&connector, &opts.host, &opts.connect).await {
+        Ok((_, stream)) => Some(stream),
+        Err(e) => {
+            debug!(target: "ssl", "handshake rejected: {e}");
+            None
+        }
+    }
+}
+
+/// Ciphers the server accepts for the version, in the order it picked them.
+/// Each round excludes the previously picked ciphers until the handshake fails.
+async fn accepted_ciphers(opts: &Opts, version: SslVersion) -> Result<Vec<Cipher>> {
+    let mut accepted: Vec<Cipher> = Vec::new();
+    loop {
+        let mut builder = connector(Some(version))?;
+        if version == SslVersion::TLS1_3 {
+            let remaining = TLS13_CIPHERS
+                .iter()
+                .filter(|name| !accepted.iter().any(|c| c.name == **name))
+                .cloned()
+                .collect::<Vec<_>>();
+            if remaining.is_empty() || builder.set_ciphersuites(&remaining.join(":")).is_err() {
+                break;
+            }
+        } else {
+            let excluded = accepted
+                .iter()
+                .map(|c| format!(":!{}", c.name))
+                .collect::<String>();
+            // fails once no cipher is left to offer
+            if builder
+                .set_cipher_list(&format!("{LEGACY_CIPHERS}{excluded}:@SECLEVEL=0"))
+                .is_err()
+            {
+                break;
+            }
+        }
+
+        let Some(stream) = handshake(opts, builder.build()).await else {
+            break;
+        };
+        match stream.ssl().current_cipher().map(Cipher::from) {
+            Some(cipher) if !accepted.iter().any(|c| c.name == cipher.name) => {
+                accepted.push(cipher)
+            }
+            _ => break,
+        }
+    }
+    Ok(accepted)
+}
+
+/// Offer the accepted ciphers in reverse, a server enforcing its own order still picks its favourite
+async fn server_enforces_order(
+    opts: &Opts,
+    version: SslVersion,
+    ciphers: &[Cipher],
+) -> Result<bool> {
+    let reversed = ciphers
+        .iter()
+        .rev()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>()
+        .join(":");
+    let mut builder = connector(Some(version))?;
+    if version == SslVersion::TLS1_3 {
+        builder.set_ciphersuites(&reversed)?;
+    } else {
+        builder.set_cipher_list(&format!("{reversed}:@SECLEVEL=0"))?;
+    }
+    let picked = handshake(opts, builder.build())
+        .await
+        .and_then(|s| s.ssl().current_cipher().map(|c| c.name().to_string()));
+    Ok(picked.as_deref() == Some(ciphers[0].name.as_str()))
+}
+
+/// Groups the server accepts for key exchange, probed with the given version
+async fn accepted_groups(opts: &Opts, version: SslVersion) -> Result<Vec<String>> {
+    let mut accepted = Vec::new();
+    for (group, tls13_only) in GROUPS {
+        if tls13_only && version != SslVersion::TLS1_3 {
+            continue;
+        }
+        let mut builder = connector(Some(version))?;
+        if version != SslVersion::TLS1_3 {
+            // only ECDHE key exchange uses the offered groups before TLS 1.3
+            builder.set_cipher_list("ECDHE:@SECLEVEL=0")?;
+        }
+        if builder.set_groups_list(group).is_err() {
+            debug!(target: "ssl", "group {group} is not supported by the local OpenSSL");
+            continue;
+        }
+        if handshake(opts, builder.build()).await.is_some() {
+            accepted.push(group.to_string());
+        }
+    }
+    Ok(accepted)
+}
+
+fn version_weakness(version: SslVersion) -> Option<&'static str> {
+    match version {
+        v if v == SslVersion::TLS1 || v == SslVersion::TLS1_1 => {
+            Some("deprecated protocol version")
+        }
+        _ => None,
+    }
+}
+
+/// A negotiated cipher, with the fields of OpenSSL's description of it
+struct Cipher {
+    name: String,
+    kx: String,
+    au: String,
+    enc: String,
+    mac: String,
+}
+
+impl From<&SslCipherRef> for Cipher {
+    fn from(cipher: &SslCipherRef) -> Self {
+        // e.g. "ECDHE-RSA-AES128-GCM-SHA256 TLSv1.2 Kx=ECDH Au=RSA Enc=AESGCM(128) Mac=AEAD"
+        let description = cipher.description();
+        let field = |key: &str| {
+            description
+                .split_whitespace()
+                .find_map(|f| f.strip_prefix(key))
+                .unwrap_or("?")
+                .to_string()
+        };
+        Self {
+            name: cipher.name().to_string(),
+            kx: field("Kx="),
+            au: field("Au="),
+            enc: field("Enc="),
+            mac: field("Mac="),
+        }
+    }
+}
+
+impl Cipher {
+    /// Reasons the cipher is considered weak when used with the version
+    fn weaknesses(&self, version: SslVersion) -> Vec<&'static str> {
+        let mut reasons = Vec::new();
+        if self.enc.starts_with("RC4") {
+            reasons.push("RC4");
+        }
+        if self.enc.starts_with("3DES") || self.enc.starts_with("DES") {
+            reasons.push("DES/3DES");
+        }
+        if self.enc == "None" {
+            reasons.push("no encryption");
+        }
+        if self.au == "None" {
+            reasons.push("no authentication");
+        }
+        if !["ECDH", "DH", "ECDHEPSK", "DHEPSK", "any"].contains(&self.kx.as_str()) {
+            reasons.push("no forward secrecy");
+        }
+        if version == SslVersion::TLS1
+            && self.mac != "AEAD"
+            && !self.enc.starts_with("RC4")
+            && self.enc != "None"
+        {
+            reasons.push("CBC with TLS 1.0");
+        }
+        reasons
+    }
+}
@@ -0,0 +1,491 @@
+//! A TLS server logging what each client offers in its handshake
+use crate::*;
+use openssl::{
+    ssl::{
+        select_next_proto, AlpnError, Ssl, SslAcceptor, SslContextRef, SslMethod, SslOptions,
+        SslRef, SslVerifyMode,
+    },
+    x509::X509,
+};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// address to listen on
+    #[clap(short, long, default_value = "0.0.0.0:8443")]
+    listen: SocketAddr,
+    /// server certificate (PEM, may include its chain), a self-signed one is generated without it
+    #[clap(long, value_name = "PATH")]
+    cert: Option<PathBuf>,
+    /// private key (PEM) of the certificate, defaults to the certificate file
+    #[clap(long, value_name = "PATH", requires = "cert")]
+    key: Option<PathBuf>,
+    /// names the generated certificate is valid for
+    #[clap(long, default_value = "localhost", conflicts_with = "cert")]
+    san: Vec<String>,
+    /// ALPN protocols to accept, comma separated in order of preference (e.g. `h2,http/1.1`)
+    #[clap(long, value_name = "PROTOCOLS")]
+    alpn: Option<String>,
+    /// ask clients for a certificate, any certificate is accepted
+    #[clap(long)]
+    request_cert: bool,
+    /// also accept TLS 1.0/1.1 and every cipher the local OpenSSL knows, for old clients
+    #[clap(long)]
+    legacy: bool,
+    /// echo back whatever the client sends after the handshake
+    #[clap(long, conflicts_with = "http")]
+    echo: bool,
+    /// answer each request with a fixed HTTP response with this body
+    #[clap(long, value_name = "BODY", num_args = 0..=1, default_missing_value = "OK\n")]
+    http: Option<String>,
+    /// ClientHello and handshake timeout in milliseconds
+    #[clap(long, default_value = "5000")]
+    timeout: u64,
+}
+
+/// Accepts connections until interrupted, the connection is closed after the handshake
+/// unless `--echo` or `--http` is given
+pub async fn main(opts: &Opts) -> Result<()> {
+    let acceptor = Arc::new(build_acceptor(opts)?);
+    let listener = tokio::net::TcpListener::bind(opts.listen)
+        .await
+        .map_err(|e| anyhow!("failed to listen on {}: {e}", opts.listen))?;
+    println!("Listening on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let stream = stream.into_std()?;
+        stream.set_nonblocking(false)?;
+        let (acceptor, opts) = (acceptor.clone(), opts.clone());
+        tokio::task::spawn_blocking(move || {
+            let mut log = vec![format!("Client:   {peer}")];
+            if let Err(e) = handle(&opts, &acceptor, stream, &mut log) {
+                log.push(format!("Error:    {e}"));
+            }
+            // printed at once so concurrent clients don't interleave
+            println!("\n{}", log.join("\n"));
+        });
+    }
+}
+
+fn build_acceptor(opts: &Opts) -> Result<SslAcceptor> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    if opts.legacy {
+        builder.set_security_level(0);
+        builder.clear_options(SslOptions::NO_TLSV1 | SslOptions::NO_TLSV1_1);
+        builder.set_min_proto_version(None)?;
+        builder.set_cipher_list("ALL:COMPLEMENTOFALL:@SECLEVEL=0")?;
+    }
+
+    match &opts.cert {
+        Some(cert) => {
+            let certs = super::file::read_certs(cert)?;
+            let key = super::file::read_key(opts.key.as_deref().unwrap_or(cert), None)?;
+            builder.set_certificate(&certs[0])?;
+            for cert in certs.iter().skip(1) {
+                builder.add_extra_chain_cert(cert.to_owned())?;
+            }
+            builder.set_private_key(&key)?;
+        }
+        None => {
+            let (cert, key) = super::gen::self_signed(&opts.san)?;
+            println!(
+                "Generated a self-signed certificate for {}",
+                opts.san.join(", ")
+            );
+            builder.set_certificate(&cert)?;
+            builder.set_private_key(&key)?;
+        }
+    }
+    builder
+        .check_private_key()
+        .map_err(|e| anyhow!("private key does not match the certificate: {e}"))?;
+
+    if let Some(alpn) = &opts.alpn {
+        // the selected protocol is borrowed from this buffer, which must outlive the acceptor
+        let protocols: &'static [u8] = Vec::leak(super::alpn_wire_format(alpn)?);
+        builder.set_alpn_select_callback(move |_, client| {
+            select_next_proto(protocols, client).ok_or(AlpnError::NOACK)
+        });
+    }
+    if opts.request_cert {
+        builder.set_verify_callback(SslVerifyMode::PEER, |_, _| true);
+    }
+    Ok(builder.build())
+}
+
+/// Log the ClientHello, then complete the handshake and serve the connection
+fn handle(
+    opts: &Opts,
+    acceptor: &SslAcceptor,
+    stream: TcpStream,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    let timeout = Duration::from_millis(opts.timeout);
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let hello = ClientHello::parse(&peek_client_hello(&stream, timeout)?)?;
+    log_client_hello(&hello, acceptor.context(), log)?;
+
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut stream = ssl
+        .accept(stream)
+        .map_err(|e| anyhow!("handshake failed: {e}"))?;
+    log_session(stream.ssl(), log);
+
+    stream.get_ref().set_read_timeout(None)?;
+    stream.get_ref().set_write_timeout(None)?;
+    if opts.echo {
+        let mut buf = [0; 4096];
+        loop {
+            match stream.read(&mut buf)? {
+                0 => break,
+                n => stream.write_all(&buf[..n])?,
+            }
+        }
+    } else if let Some(body) = &opts.http {
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        if !request.is_empty() {
+            log.push(format!("Request:  {}", request.trim_end()));
+        }
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        let stream = reader.get_mut();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.shutdown()?;
+    } else {
+        stream.shutdown()?;
+    }
+    Ok(())
+}
+
+fn log_client_hello(hello: &ClientHello, ctx: &SslContextRef, log: &mut Vec<String>) -> Result<()> {
+    // cipher names are looked up in the local OpenSSL, whether or not they are enabled
+    let ssl = Ssl::new(ctx)?;
+    let ciphers = hello
+        .ciphers
+        .iter()
+        .map(|id| {
+            ssl.bytes_to_cipher_list(&id.to_be_bytes(), false)
+                .ok()
+                .and_then(|lists| lists.suites.iter().next().map(|c| c.name().to_string()))
+                .unwrap_or_else(|| match id {
+                    0x00ff => "TLS_EMPTY_RENEGOTIATION_INFO_SCSV".to_string(),
+                    0x5600 => "TLS_FALLBACK_SCSV".to_string(),
+                    _ => display_id(*id),
+                })
+        })
+        .collect::<Vec<_>>();
+    let versions = match hello.versions.is_empty() {
+        true => vec![version_name(hello.legacy_version)],
+        false => hello.versions.iter().map(|v| version_name(*v)).collect(),
+    };
+
+    log.push(format!(
+        "SNI:      {}",
+        hello.sni.as_deref().unwrap_or("<none>")
+    ));
+    log.push(format!("Versions: {}", versions.join(", ")));
+    log.push(format!("Ciphers:  {}", super::display_list(&ciphers)));
+    log.push(format!(
+        "Groups:   {}",
+        super::display_list(
+            &hello
+                .groups
+                .iter()
+                .map(|g| group_name(*g))
+                .collect::<Vec<_>>()
+        )
+    ));
+    log.push(format!("ALPN:     {}", super::display_list(&hello.alpn)));
+    Ok(())
+}
+
+fn log_session(ssl: &SslRef, log: &mut Vec<String>) {
+    log.push(format!("Protocol: {}", ssl.version_str()));
+    if let Some(cipher) = ssl.current_cipher() {
+        log.push(format!("Cipher:   {}", cipher.name()));
+    }
+    log.push(format!(
+        "ALPN:     {}",
+        ssl.selected_alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).to_string())
+            .unwrap_or("<none>".to_string())
+    ));
+    log.push(format!(
+        "Cert:     {}",
+        match ssl.peer_certificate() {
+            Some(cert) => display_client_cert(&cert),
+            None => "<none>".to_string(),
+        }
+    ));
+}
+
+fn display_client_cert(cert: &X509) -> String {
+    format!(
+        "{} (issuer: {}, expires {})",
+        super::display_name_inline(cert.subject_name()),
+        super::display_name_inline(cert.issuer_name()),
+        cert.not_after()
+    )
+}
+
+/// Peek at the start of the connection until the whole ClientHello has arrived,
+/// leaving it in place for the handshake
+fn peek_client_hello(stream: &TcpStream, timeout: Duration) -> Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = stream
+            .peek(&mut buf)
+            .map_err(|e| anyhow!("failed to read the ClientHello: {e}"))?;
+        ensure!(n > 0, "connection closed before the ClientHello");
+        match handshake_message(&buf[..n])? {
+            Some(message) => return Ok(message),
+            None if n == buf.len() => bail!("ClientHello is too large"),
+            None if Instant::now() > deadline => bail!("timed out waiting for the ClientHello"),
+            // peek returns straight away while any data is buffered
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+/// Reassemble the first handshake message from the records, `None` until all of it has arrived
+fn handshake_message(records: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut records = Reader(records);
+    let mut message = Vec::new();
+    while records.0.len() >= 5 {
+        if message.is_empty() && records.0[0] & 0x80 != 0 {
+            bail!("SSLv2-compatible ClientHello, the client only supports SSLv2 or is very old");
+        }
+        ensure!(
+            records.0[0] == 0x16,
+            "not a TLS handshake, the client sent {}",
+            super::display_hex_bytes(&records.0[..records.0.len().min(16)])
+        );
+        records.u8()?;
+        records.bytes(2)?;
+        let len = records.u16()? as usize;
+        if records.0.len() < len {
+            return Ok(None);
+        }
+        message.extend_from_slice(records.bytes(len)?);
+        if message.len() >= 4 {
+            let message_len = u32::from_be_bytes([0, message[1], message[2], message[3]]) as usize;
+            if message.len() >= 4 + message_len {
+                message.truncate(4 + message_len);
+                return Ok(Some(message));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// What a client offered in its ClientHello
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClientHello {
+    legacy_version: u16,
+    sni: Option<String>,
+    /// the supported_versions extension, empty for clients older than TLS 1.3
+    versions: Vec<u16>,
+    ciphers: Vec<u16>,
+    groups: Vec<u16>,
+    alpn: Vec<String>,
+}
+
+impl ClientHello {
+    /// Parse a ClientHello handshake message
+    fn parse(message: &[u8]) -> Result<Self> {
+        let mut message = Reader(message);
+        ensure!(
+            message.u8()? == 1,
+            "the first handshake message is not a ClientHello"
+        );
+        message.bytes(3)?;
+        let legacy_version = message.u16()?;
+        message.bytes(32)?; // random
+        message.vec8()?; // session id
+        let ciphers = message.vec16()?.u16s()?;
+        message.vec8()?; // compression methods
+
+        let mut hello = Self {
+            legacy_version,
+            sni: None,
+            versions: Vec::new(),
+            ciphers,
+            groups: Vec::new(),
+            alpn: Vec::new(),
+        };
+        // extensions are optional before TLS 1.3
+        if message.0.is_empty() {
+            return Ok(hello);
+        }
+        let mut extensions = message.vec16()?;
+        while !extensions.0.is_empty() {
+            let ext_type = extensions.u16()?;
+            let mut data = extensions.vec16()?;
+            match ext_type {
+                0 => {
+                    let mut names = data.vec16()?;
+                    while !names.0.is_empty() {
+                        let name_type = names.u8()?;
+                        let name = names.vec16()?;
+                        if name_type == 0 {
+                            hello.sni = Some(String::from_utf8_lossy(name.0).to_string());
+                        }
+                    }
+                }
+                10 => hello.groups = data.vec16()?.u16s()?,
+                16 => {
+                    let mut protocols = data.vec16()?;
+                    while !protocols.0.is_empty() {
+                        hello
+                            .alpn
+                            .push(String::from_utf8_lossy(protocols.vec8()?.0).to_string());
+                    }
+                }
+                43 => hello.versions = data.vec8()?.u16s()?,
+                _ => {}
+            }
+        }
+        Ok(hello)
+    }
+}
+
+/// Reads the big-endian fields and length-prefixed vectors of TLS messages
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(self.0.len() >= n, "truncated ClientHello");
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn vec8(&mut self) -> Result<Reader<'a>> {
+        let len = self.u8()? as usize;
+        Ok(Reader(self.bytes(len)?))
+    }
+
+    fn vec16(&mut self) -> Result<Reader<'a>> {
+        let len = self.u16()? as usize;
+        Ok(Reader(self.bytes(len)?))
+    }
+
+    fn u16s(mut self) -> Result<Vec<u16>> {
+        let mut values = Vec::with_capacity(self.0.len() / 2);
+        while !self.0.is_empty() {
+            values.push(self.u16()?);
+        }
+        Ok(values)
+    }
+}
+
+/// GREASE values (RFC 8701) are random placeholders clients send to keep servers tolerant
+fn is_grease(id: u16) -> bool {
+    id & 0x0f0f == 0x0a0a && id >> 8 == id & 0xff
+}
+
+fn display_id(id: u16) -> String {
+    match is_grease(id) {
+        true => "GREASE".to_string(),
+        false => format!("0x{id:04x}"),
+    }
+}
+
+fn version_name(version: u16) -> String {
+    match version {
+        0x0304 => "TLSv1.3".to_string(),
+        0x0303 => "TLSv1.2".to_string(),
+        0x0302 => "TLSv1.1".to_string(),
+        0x0301 => "TLSv1.0".to_string(),
+        0x0300 => "SSLv3".to_string(),
+        _ => display_id(version),
+    }
+}
+
+fn group_name(group: u16) -> String {
+    match group {
+        0x0017 => "P-256".to_string(),
+        0x0018 => "P-384".to_string(),
+        0x0019 => "P-521".to_string(),
+        0x001d => "X25519".to_string(),
+        0x001e => "X448".to_string(),
+        0x0100 => "ffdhe2048".to_string(),
+        0x0101 => "ffdhe3072".to_string(),
+        0x0102 => "ffdhe4096".to_string(),
+        0x0103 => "ffdhe6144".to_string(),
+        0x0104 => "ffdhe8192".to_string(),
+        0x11ec => "X25519MLKEM768".to_string(),
+        0x6399 => "X25519Kyber768Draft00".to_string(),
+        _ => display_id(group),
+    }
+}
+
+#[test]
+fn test_client_hello_parse() -> Result<()> {
+    use openssl::ssl::{HandshakeError, SslConnector};
+
+    /// Captures what the client writes, reads never complete
+    struct Capture(Vec<u8>);
+    impl Read for Capture {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+    }
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    builder.set_alpn_protos(b"\x02h2\x08http/1.1")?;
+    builder.set_groups_list("X25519:P-256")?;
+    let written = match builder
+        .build()
+        .configure()?
+        .connect("example.com", Capture(Vec::new()))
+    {
+        Err(HandshakeError::WouldBlock(mid)) => mid.get_ref().0.clone(),
+        _ => bail!("expected the handshake to wait for the server"),
+    };
+
+    let message = handshake_message(&written)?.ok_or(anyhow!("incomplete ClientHello"))?;
+    let hello = ClientHello::parse(&message)?;
+    assert_eq!(hello.legacy_version, 0x0303);
+    assert_eq!(hello.sni.as_deref(), Some("example.com"));
+    assert!(hello.versions.contains(&0x0304));
+    assert_eq!(hello.groups, [0x001d, 0x0017]);
+    assert_eq!(hello.alpn, ["h2", "http/1.1"]);
+    assert!(!hello.ciphers.is_empty());
+    assert_eq!(handshake_message(&written[..written.len() - 1])?, None);
+    Ok(())
+}
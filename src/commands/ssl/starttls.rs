@@ -0,0 +1,235 @@
+//! Plaintext exchanges that upgrade a connection to TLS
+use crate::*;
+use std::io::{Read, Write};
+
+/// Schemes that negotiate TLS with STARTTLS
+pub const SCHEMES: [&str; 7] = ["smtp", "imap", "pop3", "ftp", "ldap", "xmpp", "postgres"];
+
+/// The LDAP StartTLS extended operation OID
+const LDAP_STARTTLS_OID: &[u8] = b"1.3.6.1.4.1.1466.20037";
+
+/// The PostgreSQL SSLRequest code
+const POSTGRES_SSL_REQUEST: u32 = 80877103;
+
+/// Largest line, reply or message accepted from the server, far above what a greeting
+/// or capability list needs, so a broken or hostile server can't exhaust memory
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+
+pub fn is_supported(scheme: &str) -> bool {
+    scheme == "postgresql" || SCHEMES.contains(&scheme)
+}
+
+/// Run the protocol's STARTTLS exchange, leaving the stream ready for the TLS handshake
+pub fn upgrade(scheme: &str, stream: &mut TcpStream, host: &str) -> Result<()> {
+    match scheme {
+        "smtp" => smtp(stream),
+        "imap" => imap(stream),
+        "pop3" => pop3(stream),
+        "ftp" => ftp(stream),
+        "ldap" => ldap(stream),
+        "xmpp" => xmpp(stream, host),
+        "postgres" | "postgresql" => postgres(stream),
+        _ => bail!("STARTTLS is not supported for '{scheme}'"),
+    }
+}
+
+fn smtp(stream: &mut TcpStream) -> Result<()> {
+    expect_reply(stream, "220")?;
+    stream.write_all(b"EHLO toolbelt\r\n")?;
+    let capabilities = expect_reply(stream, "250")?;
+    if !capabilities.iter().any(|l| {
+        l.get(4..)
+            .is_some_and(|c| c.trim().eq_ignore_ascii_case("STARTTLS"))
+    }) {
+        bail!("server does not advertise STARTTLS");
+    }
+    stream.write_all(b"STARTTLS\r\n")?;
+    expect_reply(stream, "220")?;
+    Ok(())
+}
+
+fn imap(stream: &mut TcpStream) -> Result<()> {
+    let greeting = read_line(stream)?;
+    ensure!(
+        greeting.starts_with("* OK"),
+        "unexpected greeting '{greeting}'"
+    );
+    stream.write_all(b"a001 STARTTLS\r\n")?;
+    loop {
+        // skip untagged responses
+        let line = read_line(stream)?;
+        if let Some(status) = line.strip_prefix("a001 ") {
+            ensure!(
+                status.starts_with("OK"),
+                "server refused STARTTLS: '{line}'"
+            );
+            return Ok(());
+        }
+    }
+}
+
+fn pop3(stream: &mut TcpStream) -> Result<()> {
+    let greeting = read_line(stream)?;
+    ensure!(
+        greeting.starts_with("+OK"),
+        "unexpected greeting '{greeting}'"
+    );
+    stream.write_all(b"STLS\r\n")?;
+    let line = read_line(stream)?;
+    ensure!(line.starts_with("+OK"), "server refused STLS: '{line}'");
+    Ok(())
+}
+
+fn ftp(stream: &mut TcpStream) -> Result<()> {
+    expect_reply(stream, "220")?;
+    stream.write_all(b"AUTH TLS\r\n")?;
+    expect_reply(stream, "234")?;
+    Ok(())
+}
+
+fn ldap(stream: &mut TcpStream) -> Result<()> {
+    // LDAPMessage { messageID 1, ExtendedRequest { requestName [0] OID } }
+    let oid_len = LDAP_STARTTLS_OID.len() as u8;
+    let mut request = vec![0x30, oid_len + 7, 0x02, 0x01, 0x01, 0x77, oid_len + 2];
+    request.extend([0x80, oid_len]);
+    request.extend(LDAP_STARTTLS_OID);
+    stream.write_all(&request)?;
+
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    ensure!(header[0] == 0x30, "unexpected LDAP response");
+    let len = match header[1] {
+        len if len & 0x80 == 0 => len as usize,
+        long_form => {
+            let mut len_bytes = vec![0; (long_form & 0x7f) as usize];
+            stream.read_exact(&mut len_bytes)?;
+            len_bytes
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        }
+    };
+    ensure!(
+        len <= MAX_RESPONSE_LEN,
+        "LDAP response of {len} bytes is larger than {MAX_RESPONSE_LEN} bytes"
+    );
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+
+    // skip the messageID, then the ExtendedResponse starts with its resultCode
+    let id_len = *body.get(1).ok_or(anyhow!("truncated LDAP response"))? as usize;
+    let response = body
+        .get(2 + id_len..)
+        .ok_or(anyhow!("truncated LDAP response"))?;
+    ensure!(
+        response.first() == Some(&0x78),
+        "unexpected LDAP response type"
+    );
+    let result_code = response
+        .windows(3)
+        .find(|w| w[0] == 0x0a && w[1] == 0x01)
+        .map(|w| w[2])
+        .ok_or(anyhow!("LDAP response has no result code"))?;
+    ensure!(
+        result_code == 0,
+        "server refused StartTLS with result code {result_code}"
+    );
+    Ok(())
+}
+
+fn xmpp(stream: &mut TcpStream, host: &str) -> Result<()> {
+    stream.write_all(
+        format!(
+            "<?xml version='1.0'?><stream:stream to='{host}' xmlns='jabber:client' \
+            xmlns:stream='http://etherx.jabber.org/streams' version='1.0'>"
+        )
+        .as_bytes(),
+    )?;
+    let features = read_until(stream, &["</stream:features>", "<stream:features/>"])?;
+    ensure!(
+        features.contains("<starttls"),
+        "server does not advertise STARTTLS"
+    );
+    stream.write_all(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")?;
+    let response = read_until(stream, &["<proceed", "<failure"])?;
+    ensure!(response.contains("<proceed"), "server refused STARTTLS");
+    // consume the rest of the proceed element
+    read_until(stream, &[">"])?;
+    Ok(())
+}
+
+fn postgres(stream: &mut TcpStream) -> Result<()> {
+    let mut request = 8u32.to_be_bytes().to_vec();
+    request.extend(POSTGRES_SSL_REQUEST.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut response = [0; 1];
+    stream.read_exact(&mut response)?;
+    match response[0] {
+        b'S' => Ok(()),
+        b'N' => bail!("server does not accept SSL connections"),
+        b => bail!("unexpected response to SSLRequest: {b:#04x}"),
+    }
+}
+
+/// Read a, possibly multi-line, `<code>-...`/`<code> ...` reply as used by SMTP and FTP
+fn expect_reply(stream: &mut TcpStream, code: &str) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut len = 0;
+    loop {
+        let line = read_line(stream)?;
+        len += line.len();
+        ensure!(
+            len <= MAX_RESPONSE_LEN,
+            "{code} reply is longer than {MAX_RESPONSE_LEN} bytes"
+        );
+        // the code is followed by ' ' on the last line and '-' on the others
+        ensure!(
+            line.starts_with(code) && matches!(line.as_bytes().get(3), Some(b' ' | b'-')),
+            "expected {code} reply, received '{line}'"
+        );
+        let last = line.as_bytes()[3] == b' ';
+        lines.push(line);
+        if last {
+            return Ok(lines);
+        }
+    }
+}
+
+/// Read a single CRLF terminated line, one byte at a time so nothing past it is consumed
+fn read_line(stream: &mut TcpStream) -> Result<String> {
+    let mut limited = (&mut *stream).take(MAX_RESPONSE_LEN as u64);
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    while !line.ends_with(b"\r\n") {
+        if limited.read(&mut byte)? == 0 {
+            ensure!(
+                line.len() < MAX_RESPONSE_LEN,
+                "line longer than {MAX_RESPONSE_LEN} bytes received"
+            );
+            bail!("connection closed by peer");
+        }
+        line.push(byte[0]);
+    }
+    line.truncate(line.len() - 2);
+    Ok(String::from_utf8_lossy(&line).to_string())
+}
+
+/// Read until any of the markers has been received
+fn read_until(stream: &mut TcpStream, markers: &[&str]) -> Result<String> {
+    let mut limited = (&mut *stream).take(MAX_RESPONSE_LEN as u64);
+    let mut received = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        if limited.read(&mut byte)? == 0 {
+            ensure!(
+                received.len() < MAX_RESPONSE_LEN,
+                "no {} within {MAX_RESPONSE_LEN} bytes received",
+                markers.join(" or ")
+            );
+            bail!("connection closed by peer");
+        }
+        received.push(byte[0]);
+        if markers.iter().any(|m| received.ends_with(m.as_bytes())) {
+            return Ok(String::from_utf8_lossy(&received).to_string());
+        }
+    }
+}
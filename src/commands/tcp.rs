@@ -1,61 +1,555 @@
 use crate::*;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use std::pin::Pin;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader, Stdin};
+use tokio_openssl::SslStream;
+
+mod relay;
+mod script;
+mod telnet;
 
 #[derive(clap::Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Option<Command>,
     /// remote host to connect to
-    #[clap(required = true)]
-    host: Host,
+    #[clap(required_unless_present = "listen")]
+    host: Option<Host>,
     /// Port of remote host to connect to
-    #[clap(required = true)]
-    port: Port,
+    #[clap(required_unless_present = "listen")]
+    port: Option<Port>,
+    /// Listen for a connection on this port instead of connecting (like `nc -l`)
+    #[clap(short, long, value_name = "PORT", conflicts_with_all = ["host", "port", "tls"])]
+    listen: Option<Port>,
+    /// Keep listening after a client disconnects, serving several clients at once.
+    /// Input from stdin is sent to every connected client.
+    #[clap(short, long, requires = "listen")]
+    keep_open: bool,
     /// Send input from stdin to the peer
     #[clap(short, long, default_value_t = true)]
     interactive: bool,
+    /// run the session over TLS, like `openssl s_client`
+    #[clap(long)]
+    tls: bool,
+    /// don't verify the server certificate
+    #[clap(long, requires = "tls")]
+    insecure: bool,
+    /// server name to send (SNI) and verify the certificate against, defaults to the host
+    #[clap(long, value_name = "NAME", requires = "tls")]
+    sni: Option<String>,
+    /// Run a send/expect script over the connection instead of reading stdin,
+    /// exiting with an error when an expectation isn't met
+    #[clap(long, value_name = "PATH", conflicts_with = "listen")]
+    script: Option<PathBuf>,
+    /// Answer telnet option negotiation and hide telnet commands from the output
+    #[clap(long, conflicts_with_all = ["keep_open", "script"])]
+    telnet: bool,
+    #[clap(flatten)]
+    data: DataOpts,
+}
+
+/// Options for how received data is shown and how stdin is sent
+#[derive(clap::Args, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct DataOpts {
+    /// Show received data as a hexdump
+    #[clap(long, conflicts_with = "escape")]
+    hex: bool,
+    /// Show control characters and non-ASCII bytes of received data as escapes (e.g. `\r\n`, `\x00`)
+    #[clap(long)]
+    escape: bool,
+    /// How stdin is sent: `raw`, or each line as `hex` (`00 0d 0a`) or `escape` sequences (`\x00\r\n`)
+    #[clap(long, value_name = "FORMAT", default_value = "raw")]
+    input: InputFormat,
+    /// Send each keystroke as it's typed instead of whole lines, press Ctrl-] to quit
+    #[clap(long)]
+    char_mode: bool,
+    /// Line ending sent for newlines typed or read from stdin: `lf`, `crlf` or `cr`.
+    /// Defaults to `crlf` with `--telnet` and `lf` otherwise.
+    #[clap(long, value_name = "ENDING")]
+    line_ending: Option<LineEnding>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Command {
+    /// Forward connections to a backend, optionally logging the traffic
+    Relay(relay::Opts),
 }
 
-#[allow(unreachable_code)]
 pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
-    let addr = {
-        let host = opts.host.get_ip().await?;
-        let port = opts.port.0;
-        SocketAddr::from_str(&format!("{host}:{port}"))
-            .map_err(|e| anyhow!("invalid socket addr '{host}:{port}': {e}"))
-    }?;
+    if let Some(cmd) = &opts.cmd {
+        return match cmd {
+            Command::Relay(opts) => relay::main(opts).await,
+        };
+    }
+    if let Some(port) = &opts.listen {
+        return listen(opts, port).await;
+    }
+    // a broken script should fail before connecting
+    let script = opts
+        .script
+        .as_deref()
+        .map(script::Script::read)
+        .transpose()?;
+    if opts.telnet && opts.data.line_ending.is_none() {
+        opts.data.line_ending = Some(LineEnding::CrLf);
+    }
+    let (Some(host), Some(port)) = (&mut opts.host, &opts.port) else {
+        bail!("a host and port are required unless listening");
+    };
+    let addr = resolve_addr(host, port).await?;
     info!(target: "client", "Connecting to {addr}");
     let stream = tokio::net::TcpStream::connect(addr).await?;
     info!(target: "client", "Connected to {addr}");
-    let (mut reader, mut writer) = stream.into_split();
 
+    match (opts.tls, &script) {
+        (true, Some(script)) => {
+            script
+                .run(tls_handshake(opts, stream).await?, opts.data)
+                .await
+        }
+        (true, None) => session(opts, tls_handshake(opts, stream).await?).await,
+        (false, Some(script)) => script.run(stream, opts.data).await,
+        (false, None) => session(opts, stream).await,
+    }
+}
+
+/// Resolve the host, logging where its address came from
+pub async fn resolve_addr(host: &mut Host, port: &Port) -> anyhow::Result<SocketAddr> {
+    let ip = host.get_ip().await?;
+    if let Some(
+        source @ (ResolutionSource::HostsFile { .. } | ResolutionSource::NameServer { .. }),
+    ) = &host.source
+    {
+        info!(target: "client", "Resolved {host} to {ip} via {source}");
+    }
+    Ok(SocketAddr::new(ip, port.0))
+}
+
+async fn tls_handshake(
+    opts: &Opts,
+    stream: tokio::net::TcpStream,
+) -> anyhow::Result<SslStream<tokio::net::TcpStream>> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if opts.insecure {
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+    let server_name = match (&opts.sni, &opts.host) {
+        (Some(sni), _) => sni.clone(),
+        (None, Some(host)) => host.input.clone(),
+        (None, None) => bail!("a server name is required for TLS"),
+    };
+    // IP addresses are verified against the certificate but not sent as SNI
+    let ssl = builder
+        .build()
+        .configure()?
+        .verify_hostname(!opts.insecure)
+        .into_ssl(&server_name)?;
+
+    let mut stream = SslStream::new(ssl, stream)?;
+    Pin::new(&mut stream).connect().await.map_err(|e| {
+        match stream.ssl().verify_result().as_raw() {
+            0 => anyhow!("TLS handshake with '{server_name}' failed: {e}"),
+            _ => anyhow!(
+                "certificate verification failed for '{server_name}': {}",
+                stream.ssl().verify_result().error_string()
+            ),
+        }
+    })?;
+    info!(
+        target: "client",
+        "TLS session established: {}, {}",
+        stream.ssl().version_str(),
+        stream
+            .ssl()
+            .current_cipher()
+            .map(|c| c.name())
+            .unwrap_or("<none>")
+    );
+    Ok(stream)
+}
+
+/// Copy stdin to the peer and the peer to stdout until either side closes
+#[allow(unreachable_code)]
+async fn session<S>(opts: &Opts, stream: S) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+
+    // stdin and telnet replies are both sent to the peer, once every sender is gone
+    // the queued data is written and the write half shut down
+    let (peer_tx, mut peer_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(64);
+    let peer_writer = tokio::spawn(async move {
+        while let Some(data) = peer_rx.recv().await {
+            writer.write_all(&data).await?;
+        }
+        writer.shutdown().await?;
+        anyhow::Ok(())
+    });
+    // telnet replies don't keep the channel open, stdin decides when sending ends
+    let replies_tx = peer_tx.downgrade();
+
+    // without stdin, `peer_tx` lives as long as the session
     if opts.interactive {
+        let mut input = Input::new(opts.data)?;
+        let (telnet, char_mode) = (opts.telnet, opts.data.char_mode);
+        let listening = opts.listen.is_some();
         tokio::spawn(async move {
-            let mut buf = [0; 1024];
             loop {
-                let n: usize = tokio::io::stdin().read(&mut buf).await?;
-                if n == 0 {
-                    info!(target: "client", "Received EOF from stdin, closing connection to peer");
-                    exit(0);
-                }
-                writer.write_all(&buf[0..n]).await?;
+                let Some(data) = input.next().await? else {
+                    // exiting before the queued input is written would cut it short
+                    drop(peer_tx);
+                    peer_writer.await??;
+                    match (char_mode, listening) {
+                        (true, _) => close("Ctrl-] pressed, closing connection to peer"),
+                        // like `--keep-open`, a listener serves the client until it disconnects
+                        (false, true) => {
+                            info!(target: "server", "Received EOF from stdin, no longer sending to peer");
+                            break;
+                        }
+                        (false, false) => {
+                            close("Received EOF from stdin, closing connection to peer")
+                        }
+                    }
+                };
+                peer_tx
+                    .send(match telnet {
+                        true => telnet::escape_iac(&data),
+                        false => data,
+                    })
+                    .await?;
             }
             anyhow::Ok(())
         });
     }
 
+    let mut telnet = opts.telnet.then(telnet::Telnet::default);
     let mut buf = [0; 1024];
+    let mut offset = 0;
     loop {
         let n = reader.read(&mut buf).await?;
         if n == 0 {
-            info!(target: "client", "Connection closed by peer");
-            exit(0);
+            close("Connection closed by peer");
         }
-        tokio::io::stdout().write_all(&buf[0..n]).await?;
+        let data = match telnet.as_mut() {
+            Some(telnet) => {
+                let (data, replies) = telnet.receive(&buf[0..n]);
+                if let Some(replies_tx) = replies_tx.upgrade().filter(|_| !replies.is_empty()) {
+                    replies_tx.send(replies).await?;
+                }
+                data
+            }
+            None => buf[0..n].to_vec(),
+        };
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(&opts.data.render(&data, offset)).await?;
+        // prompts such as `login: ` don't end with a newline
+        stdout.flush().await?;
+        offset += data.len();
     }
 
     Ok(())
 }
 
+/// Leave the terminal as it was and exit
+fn close(message: &str) -> ! {
+    let _ = crossterm::terminal::disable_raw_mode();
+    info!(target: "client", "{message}");
+    exit(0);
+}
+
+/// Accept a connection and run a session with it, or with `--keep-open` serve clients until interrupted
+async fn listen(opts: &Opts, port: &Port) -> anyhow::Result<()> {
+    // `[::]` also accepts IPv4 clients where the socket is dual-stack, IPv4 only hosts can't bind it
+    let listener = match tokio::net::TcpListener::bind((std::net::Ipv6Addr::UNSPECIFIED, port.0))
+        .await
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            debug!(target: "server", "failed to listen on [::]:{}, falling back to IPv4: {e}", port.0);
+            let addr = SocketAddr::from(([0, 0, 0, 0], port.0));
+            tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| anyhow!("failed to listen on {addr}: {e}"))?
+        }
+    };
+    info!(target: "server", "Listening on {}", listener.local_addr()?);
+
+    if !opts.keep_open {
+        let (stream, peer) = listener.accept().await?;
+        info!(target: "server", "Connection from {peer}");
+        drop(listener);
+        return session(opts, stream).await;
+    }
+
+    let (stdin_tx, _) = tokio::sync::broadcast::channel::<Arc<[u8]>>(64);
+    if opts.interactive {
+        let stdin_tx = stdin_tx.clone();
+        let mut input = Input::new(opts.data)?;
+        tokio::spawn(async move {
+            while let Some(data) = input.next().await? {
+                // no receivers just means no client is connected
+                let _ = stdin_tx.send(data.into());
+            }
+            info!(target: "server", "Received EOF from stdin, no longer sending to clients");
+            anyhow::Ok(())
+        });
+    }
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        info!(target: "server", "Connection from {peer}");
+        let mut stdin_rx = stdin_tx.subscribe();
+        let data_opts = opts.data;
+        tokio::spawn(async move {
+            let (mut reader, mut writer) = stream.into_split();
+            let to_peer = async move {
+                loop {
+                    match stdin_rx.recv().await {
+                        Ok(data) => writer.write_all(&data).await?,
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                            warn!(target: "server", "{peer} fell behind, dropped {n} chunks of input")
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+                // stdin closed, keep reading from the peer
+                std::future::pending::<anyhow::Result<()>>().await
+            };
+            let from_peer = async move {
+                let mut buf = [0; 1024];
+                let mut offset = 0;
+                loop {
+                    let n = reader.read(&mut buf).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    let mut stdout = tokio::io::stdout();
+                    stdout
+                        .write_all(&data_opts.render(&buf[0..n], offset))
+                        .await?;
+                    stdout.flush().await?;
+                    offset += n;
+                }
+                anyhow::Ok(())
+            };
+            let result = tokio::select! {
+                result = to_peer => result,
+                result = from_peer => result,
+            };
+            match result {
+                Ok(()) => info!(target: "server", "Connection from {peer} closed"),
+                Err(e) => warn!(target: "server", "Connection from {peer} failed: {e}"),
+            }
+        });
+    }
+}
+
+impl DataOpts {
+    /// Render data received at the offset of the stream for stdout
+    fn render(&self, data: &[u8], offset: usize) -> Vec<u8> {
+        if self.hex {
+            format!("{}\n", utils::hexdump(data, offset)).into_bytes()
+        } else if self.escape {
+            // keep line breaks after the escaped newlines so line based protocols stay readable
+            data.split_inclusive(|b| *b == b'\n')
+                .map(|line| match line.ends_with(b"\n") {
+                    true => format!("{}\n", utils::escape_bytes(line)),
+                    false => utils::escape_bytes(line),
+                })
+                .collect::<String>()
+                .into_bytes()
+        } else {
+            data.to_vec()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum InputFormat {
+    Raw,
+    Hex,
+    Escape,
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(Self::Raw),
+            "hex" => Ok(Self::Hex),
+            "escape" => Ok(Self::Escape),
+            _ => bail!("invalid input format '{s}': must be one of 'raw', 'hex' or 'escape'"),
+        }
+    }
+}
+
+/// Stdin decoded in the input format, or keystrokes in character mode
+struct Input {
+    format: InputFormat,
+    line_ending: LineEnding,
+    stdin: BufReader<Stdin>,
+    /// keystrokes, only read in character mode
+    keys: Option<EventStream>,
+}
+
+impl Input {
+    fn new(opts: DataOpts) -> anyhow::Result<Self> {
+        let keys = match opts.char_mode {
+            true => {
+                info!(target: "client", "Sending keystrokes as they're typed, press Ctrl-] to quit");
+                crossterm::terminal::enable_raw_mode()
+                    .map_err(|e| anyhow!("failed to switch the terminal to character mode: {e}"))?;
+                Some(EventStream::new())
+            }
+            false => None,
+        };
+        Ok(Self {
+            format: opts.input,
+            line_ending: opts.line_ending.unwrap_or(LineEnding::Lf),
+            stdin: BufReader::new(tokio::io::stdin()),
+            keys,
+        })
+    }
+
+    /// The next bytes to send, `None` once stdin is closed.
+    /// Lines that fail to decode are skipped with a warning rather than ending the session.
+    async fn next(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(keys) = self.keys.as_mut() {
+            while let Some(event) = keys.next().await {
+                if let Event::Key(key) = event? {
+                    if is_quit_key(&key) {
+                        return Ok(None);
+                    }
+                    if let Some(data) = key_bytes(&key, self.line_ending) {
+                        return Ok(Some(data));
+                    }
+                }
+            }
+            return Ok(None);
+        }
+        if self.format == InputFormat::Raw {
+            let mut buf = [0; 1024];
+            let n = self.stdin.read(&mut buf).await?;
+            return Ok((n > 0).then(|| self.line_ending.translate(&buf[0..n])));
+        }
+        loop {
+            let mut line = String::new();
+            if self.stdin.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            let data = match self.format {
+                InputFormat::Hex => utils::parse_hex(line),
+                _ => utils::unescape_bytes(line),
+            };
+            match data {
+                Ok(data) if data.is_empty() => continue,
+                Ok(data) => return Ok(Some(data)),
+                Err(e) => warn!(target: "client", "{e}, line not sent"),
+            }
+        }
+    }
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        if self.keys.is_some() {
+            let _ = crossterm::terminal::disable_raw_mode();
+        }
+    }
+}
+
+/// Ctrl-], the telnet escape key, which terminals report as Ctrl-5
+fn is_quit_key(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(key.code, KeyCode::Char(']') | KeyCode::Char('5'))
+}
+
+/// The bytes a terminal sends for a key, `None` for keys with no byte sequence
+fn key_bytes(key: &KeyEvent, line_ending: LineEnding) -> Option<Vec<u8>> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let bytes: &[u8] = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return match c.to_ascii_uppercase() {
+                c @ '@'..='_' => Some(vec![c as u8 & 0x1f]),
+                _ => None,
+            };
+        }
+        KeyCode::Char(c) => return Some(c.to_string().into_bytes()),
+        KeyCode::Enter => line_ending.bytes(),
+        KeyCode::Backspace => b"\x7f",
+        KeyCode::Tab => b"\t",
+        KeyCode::BackTab => b"\x1b[Z",
+        KeyCode::Esc => b"\x1b",
+        KeyCode::Up => b"\x1b[A",
+        KeyCode::Down => b"\x1b[B",
+        KeyCode::Right => b"\x1b[C",
+        KeyCode::Left => b"\x1b[D",
+        KeyCode::Home => b"\x1b[H",
+        KeyCode::End => b"\x1b[F",
+        KeyCode::Insert => b"\x1b[2~",
+        KeyCode::Delete => b"\x1b[3~",
+        KeyCode::PageUp => b"\x1b[5~",
+        KeyCode::PageDown => b"\x1b[6~",
+        _ => return None,
+    };
+    Some(bytes.to_vec())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn bytes(&self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+            Self::Cr => b"\r",
+        }
+    }
+
+    /// Replace line feeds not already preceded by a carriage return
+    fn translate(&self, data: &[u8]) -> Vec<u8> {
+        if *self == Self::Lf {
+            return data.to_vec();
+        }
+        let mut translated = Vec::with_capacity(data.len());
+        for (idx, byte) in data.iter().enumerate() {
+            match byte {
+                b'\n' if idx == 0 || data[idx - 1] != b'\r' => {
+                    translated.extend_from_slice(self.bytes())
+                }
+                _ => translated.push(*byte),
+            }
+        }
+        translated
+    }
+}
+
+impl std::str::FromStr for LineEnding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lf" => Ok(Self::Lf),
+            "crlf" => Ok(Self::CrLf),
+            "cr" => Ok(Self::Cr),
+            _ => bail!("invalid line ending '{s}': must be one of 'lf', 'crlf' or 'cr'"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct Port(u16);
+pub struct Port(pub u16);
 
 impl std::str::FromStr for Port {
     type Err = anyhow::Error;
@@ -0,0 +1,95 @@
+use crate::*;
+use std::time::Instant;
+use tokio::net::{
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+    TcpListener,
+};
+
+#[derive(clap::Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// address to listen on, as `[ip]:port` (e.g. `:9000`)
+    #[clap(short, long, value_name = "ADDR")]
+    listen: String,
+    /// backend to forward connections to, as `host:port`
+    #[clap(short, long, value_name = "ADDR")]
+    to: String,
+    /// Log the traffic in each direction as a hexdump
+    #[clap(short = 'x', long)]
+    hexdump: bool,
+}
+
+/// Forwards each accepted connection to a new connection to the backend until interrupted
+pub async fn main(opts: &Opts) -> anyhow::Result<()> {
+    let listen = match opts.listen.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{port}"),
+        None => opts.listen.clone(),
+    };
+    let listen = SocketAddr::from_str(&listen)
+        .map_err(|e| anyhow!("invalid listen address '{}': {e}", opts.listen))?;
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| anyhow!("failed to listen on {listen}: {e}"))?;
+    info!(target: "relay", "Relaying {} to {}", listener.local_addr()?, opts.to);
+
+    loop {
+        let (client, peer) = listener.accept().await?;
+        let opts = opts.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay(&opts, client, peer).await {
+                warn!(target: "relay", "Connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn relay(opts: &Opts, client: tokio::net::TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let backend = tokio::net::TcpStream::connect(&opts.to)
+        .await
+        .map_err(|e| anyhow!("failed to connect to {}: {e}", opts.to))?;
+    let backend_addr = backend.peer_addr()?;
+    info!(target: "relay", "Connection from {peer} relayed to {backend_addr}");
+
+    let (client_reader, client_writer) = client.into_split();
+    let (backend_reader, backend_writer) = backend.into_split();
+    let upstream = format!("{peer} -> {backend_addr}");
+    let downstream = format!("{peer} <- {backend_addr}");
+    let (sent, received) = tokio::try_join!(
+        forward(opts, client_reader, backend_writer, &upstream),
+        forward(opts, backend_reader, client_writer, &downstream),
+    )?;
+    info!(
+        target: "relay",
+        "Connection from {peer} closed after {:.3}s, {sent} bytes sent, {received} bytes received",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Copy one direction until EOF, passing the half-close on, and return the bytes copied
+async fn forward(
+    opts: &Opts,
+    mut reader: OwnedReadHalf,
+    mut writer: OwnedWriteHalf,
+    direction: &str,
+) -> anyhow::Result<usize> {
+    let mut buf = [0; 8192];
+    let mut total = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            // the other side may already be gone
+            let _ = writer.shutdown().await;
+            return Ok(total);
+        }
+        if opts.hexdump {
+            info!(
+                target: "relay",
+                "{direction} {n} bytes\n{}",
+                utils::hexdump(&buf[0..n], total)
+            );
+        }
+        writer.write_all(&buf[0..n]).await?;
+        total += n;
+    }
+}
@@ -0,0 +1,274 @@
+//! Send/expect scripts run over a connection, for repeatable protocol smoke tests
+use crate::*;
+use regex::bytes::Regex;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::DataOpts;
+
+/// Timeout of `expect` steps until a `timeout` step changes it
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Steps of a script, with the line each was read from
+#[derive(Debug)]
+pub struct Script(Vec<(usize, Step)>);
+
+#[derive(Debug)]
+enum Step {
+    /// `send "EHLO test\r\n"`
+    Send(Vec<u8>),
+    /// `send-file payload.bin`, relative paths are resolved against the script's directory
+    SendFile(PathBuf),
+    /// `expect /^250 /` or `expect "250 "`, optionally followed by a timeout
+    Expect {
+        pattern: Regex,
+        timeout: Option<Duration>,
+    },
+    /// `sleep 1s`
+    Sleep(Duration),
+    /// `timeout 30s` sets the timeout of the following `expect` steps
+    Timeout(Duration),
+}
+
+impl Script {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+            .map_err(|e| anyhow!("invalid script {path:?}: {e}"))
+    }
+
+    fn parse(text: &str, dir: &Path) -> Result<Self> {
+        let mut steps = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = Step::parse(line, dir).map_err(|e| anyhow!("line {}: {e}", idx + 1))?;
+            steps.push((idx + 1, step));
+        }
+        Ok(Self(steps))
+    }
+
+    /// Run the steps in order, failing on the first expectation that isn't met
+    pub async fn run<S>(&self, stream: S, data: DataOpts) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let mut timeout = DEFAULT_TIMEOUT;
+        // received data from the start of the current line, matched up to `consumed`
+        let mut received = Vec::new();
+        let mut consumed = 0;
+        let mut offset = 0;
+        let mut buf = [0; 4096];
+
+        for (line, step) in self.0.iter() {
+            debug!(target: "script", "line {line}: {step:?}");
+            match step {
+                Step::Send(bytes) => writer.write_all(bytes).await?,
+                Step::SendFile(path) => {
+                    writer
+                        .write_all(
+                            &std::fs::read(path)
+                                .map_err(|e| anyhow!("Unable to read file '{:?}': {}", path, e))?,
+                        )
+                        .await?
+                }
+                Step::Sleep(duration) => tokio::time::sleep(*duration).await,
+                Step::Timeout(duration) => timeout = *duration,
+                Step::Expect {
+                    pattern,
+                    timeout: step_timeout,
+                } => {
+                    let step_timeout = step_timeout.unwrap_or(timeout);
+                    let deadline = Instant::now() + step_timeout;
+                    loop {
+                        // searching from `consumed` keeps the bytes before it as context,
+                        // so `^` doesn't match in the middle of a partly consumed line
+                        if let Some(m) = pattern.find_at(&received, consumed) {
+                            consumed = m.end();
+                            if let Some(idx) =
+                                received[..consumed].iter().rposition(|&b| b == b'\n')
+                            {
+                                received.drain(..=idx);
+                                consumed -= idx + 1;
+                            }
+                            break;
+                        }
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        let n = match tokio::time::timeout(remaining, reader.read(&mut buf)).await
+                        {
+                            Ok(n) => n?,
+                            Err(_) => bail!(
+                                "line {line}: expected /{}/ within {step_timeout:?}, received \"{}\"",
+                                pattern_source(pattern),
+                                utils::escape_bytes(&received[consumed..])
+                            ),
+                        };
+                        if n == 0 {
+                            bail!(
+                                "line {line}: connection closed while expecting /{}/, received \"{}\"",
+                                pattern_source(pattern),
+                                utils::escape_bytes(&received[consumed..])
+                            );
+                        }
+                        tokio::io::stdout()
+                            .write_all(&data.render(&buf[0..n], offset))
+                            .await?;
+                        offset += n;
+                        received.extend_from_slice(&buf[0..n]);
+                    }
+                }
+            }
+        }
+        info!(target: "script", "Script completed, {} steps passed", self.0.len());
+        Ok(())
+    }
+}
+
+impl Step {
+    fn parse(line: &str, dir: &Path) -> Result<Self> {
+        let (keyword, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arg = arg.trim();
+        match keyword {
+            "send" => {
+                let (data, rest) = quoted(arg)?;
+                ensure_empty(rest)?;
+                Ok(Step::Send(data))
+            }
+            "send-file" if !arg.is_empty() => Ok(Step::SendFile(dir.join(arg))),
+            "expect" => {
+                let (pattern, rest) = match arg.strip_prefix('/') {
+                    Some(arg) => {
+                        let end = arg
+                            .rfind('/')
+                            .ok_or(anyhow!("missing closing '/' of the pattern"))?;
+                        (arg[..end].to_string(), &arg[end + 1..])
+                    }
+                    None => {
+                        let (literal, rest) = quoted(arg)?;
+                        (escape_literal(&literal), rest)
+                    }
+                };
+                let rest = rest.trim();
+                Ok(Step::Expect {
+                    // `^` and `$` match at line boundaries, like reading a protocol line by line,
+                    // and `$` also matches before the `\r\n` most protocols end lines with
+                    pattern: Regex::new(&format!("(?mR){pattern}"))
+                        .map_err(|e| anyhow!("invalid pattern /{pattern}/: {e}"))?,
+                    timeout: match rest.is_empty() {
+                        true => None,
+                        false => Some(parse_duration(rest)?),
+                    },
+                })
+            }
+            "sleep" => Ok(Step::Sleep(parse_duration(arg)?)),
+            "timeout" => Ok(Step::Timeout(parse_duration(arg)?)),
+            _ => Err(anyhow!("unknown step '{line}'")),
+        }
+    }
+}
+
+/// The pattern as written in the script, without the line flags
+fn pattern_source(pattern: &Regex) -> &str {
+    pattern.as_str().trim_start_matches("(?mR)")
+}
+
+/// A pattern matching the bytes exactly, bytes outside ASCII are matched as raw bytes
+/// rather than as the Unicode code point of the same value
+fn escape_literal(literal: &[u8]) -> String {
+    literal
+        .iter()
+        .map(|b| match b.is_ascii() {
+            true => regex::escape(&(*b as char).to_string()),
+            false => format!("(?-u:\\x{b:02x})"),
+        })
+        .collect()
+}
+
+/// A double quoted string with escapes (`\r`, `\n`, `\x00`, `\"`...) and what follows it
+fn quoted(s: &str) -> Result<(Vec<u8>, &str)> {
+    let inner = s
+        .strip_prefix('"')
+        .ok_or(anyhow!("expected a double quoted string, got '{s}'"))?;
+    let mut escaped = false;
+    for (idx, c) in inner.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => {
+                return Ok((utils::unescape_bytes(&inner[..idx])?, &inner[idx + 1..]));
+            }
+            _ => escaped = false,
+        }
+    }
+    bail!("missing closing '\"' in '{s}'")
+}
+
+fn ensure_empty(rest: &str) -> Result<()> {
+    ensure!(rest.trim().is_empty(), "unexpected '{}'", rest.trim());
+    Ok(())
+}
+
+/// Durations such as `500ms`, `1s` or `2m`, plain numbers are seconds
+fn parse_duration(s: &str) -> Result<Duration> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(idx) => s.split_at(idx),
+        None => (s, "s"),
+    };
+    let value = value
+        .parse::<f64>()
+        .map_err(|e| anyhow!("invalid duration '{s}': {e}"))?;
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        _ => bail!("invalid duration '{s}': unit must be one of 'ms', 's' or 'm'"),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|e| anyhow!("invalid duration '{s}': {e}"))
+}
+
+#[test]
+fn test_script_parse() -> Result<()> {
+    let script = Script::parse(
+        "# smtp smoke test\n\
+         expect /^220 / 5s\n\
+         send \"EHLO test\\r\\n\"\n\
+         timeout 1m\n\
+         expect \"250 \"\n\
+         sleep 500ms\n\
+         send-file payload.bin\n",
+        Path::new("tests"),
+    )?;
+    assert_eq!(script.0.len(), 6);
+    assert!(matches!(
+        &script.0[0],
+        (2, Step::Expect { pattern, timeout: Some(t) })
+            if pattern_source(pattern) == "^220 " && *t == Duration::from_secs(5)
+    ));
+    assert!(matches!(&script.0[1], (3, Step::Send(data)) if data == b"EHLO test\r\n"));
+    assert!(matches!(&script.0[2], (4, Step::Timeout(t)) if *t == Duration::from_secs(60)));
+    assert!(matches!(&script.0[4], (6, Step::Sleep(t)) if *t == Duration::from_millis(500)));
+    assert!(
+        matches!(&script.0[5], (7, Step::SendFile(path)) if path == Path::new("tests/payload.bin"))
+    );
+
+    let script = Script::parse("expect /OK$/\nexpect \"\\xff+\"", Path::new(""))?;
+    assert!(
+        matches!(&script.0[0], (_, Step::Expect { pattern, .. }) if pattern.is_match(b"250 OK\r\n"))
+    );
+    assert!(
+        matches!(&script.0[1], (_, Step::Expect { pattern, .. }) if pattern.is_match(b"\xff+"))
+    );
+
+    assert!(Script::parse("send EHLO", Path::new("")).is_err());
+    assert!(Script::parse("expect /(/", Path::new("")).is_err());
+    assert!(Script::parse("sleep 1h", Path::new("")).is_err());
+    assert!(Script::parse("sleep 99999999999999999999999", Path::new("")).is_err());
+    assert!(Script::parse("recv", Path::new("")).is_err());
+    Ok(())
+}
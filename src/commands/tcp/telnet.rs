@@ -0,0 +1,133 @@
+//! Telnet (RFC 854) commands and option negotiation
+use crate::*;
+use std::collections::HashSet;
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// Strips telnet commands from received data and answers option negotiation.
+/// The server may echo and suppress go-ahead, every other option is refused.
+#[derive(Debug, Default)]
+pub struct Telnet {
+    state: State,
+    /// options the server has agreed to perform
+    remote: HashSet<u8>,
+    /// options we have agreed to perform
+    local: HashSet<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+impl Telnet {
+    /// Split received bytes into the data to show and the replies to send back.
+    /// Commands may be split across reads, so the parser keeps its state between calls.
+    pub fn receive(&mut self, bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut data = Vec::with_capacity(bytes.len());
+        let mut replies = Vec::new();
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, _) => {
+                    data.push(byte);
+                    State::Data
+                }
+                (State::Iac, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Iac, WILL | WONT | DO | DONT) => State::Negotiation(byte),
+                (State::Iac, SB) => State::Subnegotiation,
+                // NOP, go-ahead and the other commands carry nothing to show
+                (State::Iac, _) => State::Data,
+                (State::Negotiation(command), option) => {
+                    if let Some(reply) = self.negotiate(command, option) {
+                        replies.extend_from_slice(&[IAC, reply, option]);
+                    }
+                    State::Data
+                }
+                (State::Subnegotiation, IAC) => State::SubnegotiationIac,
+                (State::Subnegotiation, _) => State::Subnegotiation,
+                (State::SubnegotiationIac, SE) => State::Data,
+                (State::SubnegotiationIac, _) => State::Subnegotiation,
+            };
+        }
+        (data, replies)
+    }
+
+    /// The reply to a request, `None` when it doesn't change an option's state,
+    /// as acknowledging those would loop forever
+    fn negotiate(&mut self, command: u8, option: u8) -> Option<u8> {
+        let reply = match command {
+            WILL if [ECHO, SUPPRESS_GO_AHEAD].contains(&option) => {
+                self.remote.insert(option).then_some(DO)
+            }
+            WILL => Some(DONT),
+            WONT => self.remote.remove(&option).then_some(DONT),
+            DO if option == SUPPRESS_GO_AHEAD => self.local.insert(option).then_some(WILL),
+            DO => Some(WONT),
+            DONT => self.local.remove(&option).then_some(WONT),
+            _ => None,
+        };
+        debug!(
+            target: "telnet",
+            "received {} {option}, replied {}",
+            command_name(command),
+            reply.map(command_name).unwrap_or("nothing")
+        );
+        reply
+    }
+}
+
+/// Double every IAC byte in data to send, so it isn't taken as a command
+pub fn escape_iac(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+        escaped.push(byte);
+    }
+    escaped
+}
+
+fn command_name(command: u8) -> &'static str {
+    match command {
+        WILL => "WILL",
+        WONT => "WONT",
+        DO => "DO",
+        DONT => "DONT",
+        _ => "?",
+    }
+}
+
+#[test]
+fn test_telnet_receive() {
+    let mut telnet = Telnet::default();
+    // WILL ECHO, DO TERMINAL-TYPE, a subnegotiation, an escaped IAC, and WILL ECHO split across reads
+    let (data, replies) = telnet.receive(&[
+        IAC, WILL, ECHO, b'l', IAC, DO, 24, IAC, SB, 24, 1, IAC, SE, b'o', IAC, IAC, IAC,
+    ]);
+    assert_eq!(data, [b'l', b'o', IAC]);
+    assert_eq!(replies, [IAC, DO, ECHO, IAC, WONT, 24]);
+    let (data, replies) = telnet.receive(&[WILL, ECHO, b'g']);
+    // already agreed, so not acknowledged again
+    assert_eq!(data, b"g");
+    assert!(replies.is_empty());
+    assert_eq!(escape_iac(&[1, IAC, 2]), [1, IAC, IAC, 2]);
+}
@@ -0,0 +1,66 @@
+use crate::*;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::UdpSocket,
+    time::Instant,
+};
+
+use super::tcp::{resolve_addr, Port};
+
+#[derive(clap::Parser, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Opts {
+    /// remote host to send datagrams to
+    #[clap(required = true)]
+    host: Host,
+    /// Port of remote host to send datagrams to
+    #[clap(required = true)]
+    port: Port,
+    /// Exit once no datagram has been received for this many milliseconds
+    #[clap(short, long, value_name = "MS")]
+    timeout: Option<u64>,
+}
+
+/// Sends each line of stdin as a datagram and prints datagrams received from any address.
+/// Datagrams are still received after stdin is closed, until the timeout or an interrupt.
+pub async fn main(opts: &mut Opts) -> anyhow::Result<()> {
+    let addr = resolve_addr(&mut opts.host, &opts.port).await?;
+    let bind_addr = match addr {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    info!(target: "client", "Sending datagrams to {addr} from {}", socket.local_addr()?);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdin_open = true;
+    let mut buf = vec![0; 65535];
+    // one deadline for the whole session, only pushed back when a datagram arrives
+    let timeout = opts.timeout.map(Duration::from_millis);
+    let deadline = tokio::time::sleep_until(Instant::now() + timeout.unwrap_or_default());
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            line = lines.next_line(), if stdin_open => match line? {
+                Some(line) => {
+                    socket.send_to(line.as_bytes(), addr).await?;
+                }
+                None => {
+                    info!(target: "client", "Received EOF from stdin, still receiving datagrams");
+                    stdin_open = false;
+                }
+            },
+            received = socket.recv_from(&mut buf) => {
+                let (n, source) = received?;
+                println!("{} {}", format!("{source}:").dimmed(), String::from_utf8_lossy(&buf[..n]));
+                if let Some(timeout) = timeout {
+                    deadline.as_mut().reset(Instant::now() + timeout);
+                }
+            },
+            _ = &mut deadline, if timeout.is_some() => {
+                info!(target: "client", "No datagram received for {}ms, closing", opts.timeout.unwrap_or_default());
+                return Ok(());
+            },
+        }
+    }
+}
@@ -4,14 +4,16 @@ use std::{
 };
 
 use anyhow::anyhow;
-use trust_dns_resolver::TokioAsyncResolver;
+use tracing::debug;
 
-use crate::utils;
+use crate::{clients::prelude::*, utils};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Host {
     pub input: String,
     pub ip: Option<IpAddr>,
+    /// Where `ip` was resolved from
+    pub source: Option<ResolutionSource>,
 }
 
 impl std::str::FromStr for Host {
@@ -21,6 +23,7 @@ impl std::str::FromStr for Host {
         utils::validate_fqdn_or_ip(s).map(|_| Self {
             input: s.to_owned(),
             ip: None,
+            source: None,
         })
     }
 }
@@ -41,30 +44,19 @@ impl Host {
         if let Ok(ip) = v.parse::<IpAddr>() {
             // return IP if input already is one
             self.ip = Some(ip);
+            self.source = Some(ResolutionSource::Literal);
             return Ok(ip);
         };
-        // resolve IP from DNS
-        TokioAsyncResolver::tokio_from_system_conf()
-            .map_err(|e| anyhow!("failed to create DNS resolver: {e}"))?
-            .lookup_ip(v)
-            .await
-            .map_err(|e| {
-                anyhow!(
-                    "failed to resolve '{v}': {}",
-                    match e.kind() {
-                        trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } =>
-                            "no records found",
-                        trust_dns_resolver::error::ResolveErrorKind::Message(msg) => msg,
-                        _ => "unknown error",
-                    }
-                )
-            })?
-            .iter()
+        // resolve IP from the hosts file or DNS
+        let lookup = TracingResolver::from_system_conf()?.lookup_ip(v).await?;
+        let ip = lookup
+            .ips()
+            .into_iter()
             .next()
-            .ok_or(anyhow!("failed to resolve valid IP for '{v}'"))
-            .map(|ip| {
-                self.ip = Some(ip);
-                ip
-            })
+            .ok_or(anyhow!("failed to resolve valid IP for '{v}'"))?;
+        debug!(target: "host", "resolved '{v}' to {ip} via {}", lookup.source);
+        self.ip = Some(ip);
+        self.source = Some(lookup.source);
+        Ok(ip)
     }
 }
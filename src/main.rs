@@ -31,6 +31,7 @@ mod commands {
     mod rng;
     mod ssl;
     mod tcp;
+    mod udp;
 
     #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, clap::Subcommand)]
     pub enum Command {
@@ -39,12 +40,14 @@ mod commands {
         /// Query DNS records
         Dns(dns::Opts),
         /// Establish a SSL TCP connection and display SSL certificate
-        Ssl(ssl::Opts),
+        Ssl(Box<ssl::Opts>),
         /// Generate a random number from a range
         #[clap(alias = "roll")]
         Rng(rng::Opts),
         /// Create a TCP connection (like telnet)
         Tcp(tcp::Opts),
+        /// Send and receive UDP datagrams (like `nc -u`)
+        Udp(udp::Opts),
         /// Scan host(s) TCP port(s)
         Ports(ports::Opts),
         /// Generate an overview of a domain
@@ -61,6 +64,7 @@ mod commands {
                 Command::Ssl(opts) => ssl::main(opts).await,
                 Command::Rng(opts) => rng::main(opts).await,
                 Command::Tcp(opts) => tcp::main(opts).await,
+                Command::Udp(opts) => udp::main(opts).await,
                 Command::Ports(opts) => ports::main(opts).await,
                 Command::Domain(opts) => domain::main(opts).await,
                 Command::Diff(opts) => diff::main(opts).await,
@@ -45,6 +45,122 @@ pub fn port_from_protocol(proto: &str) -> u16 {
         "smtp" => 25,
         "pop3" => 110,
         "imap" => 143,
+        "ldap" => 389,
+        "xmpp" => 5222,
+        "postgres" | "postgresql" => 5432,
         _ => 0,
     }
 }
+
+/// Hexdump in the `hexdump -C` layout, offsets start at `offset`
+pub fn hexdump(bytes: &[u8], offset: usize) -> String {
+    let mut lines = Vec::with_capacity(bytes.len().div_ceil(16));
+    for (idx, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(49);
+        for (i, byte) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{byte:02x} "));
+        }
+        let ascii = chunk
+            .iter()
+            .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                true => *b as char,
+                false => '.',
+            })
+            .collect::<String>();
+        lines.push(format!("{:08x}  {hex:<49} |{ascii}|", offset + idx * 16));
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_hexdump() {
+    assert_eq!(
+        hexdump(b"GET / HTTP/1.1\r\nHost: a\r\n", 16),
+        "00000010  47 45 54 20 2f 20 48 54  54 50 2f 31 2e 31 0d 0a  |GET / HTTP/1.1..|\n\
+         00000020  48 6f 73 74 3a 20 61 0d  0a                       |Host: a..|"
+    );
+}
+
+/// Printable ASCII as is, everything else as an escape (`\r`, `\n`, `\t`, `\0`, `\\` or `\xNN`)
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for byte in bytes {
+        match byte {
+            b'\r' => escaped.push_str("\\r"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\0' => escaped.push_str("\\0"),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(*byte as char),
+            _ => escaped.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    escaped
+}
+
+/// Reverse of `escape_bytes`, also accepting `\e` and `\"`, other characters are kept as UTF-8
+pub fn unescape_bytes(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(b'\0'),
+            Some('e') => bytes.push(0x1b),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                bytes.push(u8::from_str_radix(&hex, 16).map_err(|_| {
+                    anyhow::anyhow!("invalid escape '\\x{hex}': expected two hex digits")
+                })?);
+            }
+            Some(c) => anyhow::bail!("invalid escape '\\{c}'"),
+            None => anyhow::bail!("trailing '\\' without an escape"),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Bytes from hex digits, ignoring whitespace and `:` separators (e.g. `de ad be ef`, `de:ad:be:ef`)
+pub fn parse_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect::<Vec<_>>();
+    anyhow::ensure!(
+        digits.len() % 2 == 0,
+        "invalid hex '{s}': odd number of digits"
+    );
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = pair.iter().collect::<String>();
+            u8::from_str_radix(&pair, 16)
+                .map_err(|_| anyhow::anyhow!("invalid hex '{s}': '{pair}' is not a hex byte"))
+        })
+        .collect()
+}
+
+#[test]
+fn test_escapes() -> anyhow::Result<()> {
+    let bytes = b"EHLO a\r\n\0\x1b\\\xff";
+    assert_eq!(escape_bytes(bytes), "EHLO a\\r\\n\\0\\x1b\\\\\\xff");
+    assert_eq!(unescape_bytes(&escape_bytes(bytes))?, bytes);
+    assert_eq!(unescape_bytes("\\e[0m")?, b"\x1b[0m");
+    assert!(unescape_bytes("\\xg0").is_err());
+    assert!(unescape_bytes("\\").is_err());
+    assert_eq!(parse_hex("de ad:BE EF")?, [0xde, 0xad, 0xbe, 0xef]);
+    assert!(parse_hex("abc").is_err());
+    assert!(parse_hex("zz").is_err());
+    Ok(())
+}